@@ -0,0 +1,5 @@
+-- Table: public.order_product
+
+ALTER TABLE public.order_product
+    DROP CONSTRAINT IF EXISTS order_product_quantity_check,
+    DROP COLUMN IF EXISTS unit_price;
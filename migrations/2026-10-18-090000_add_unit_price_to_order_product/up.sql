@@ -0,0 +1,16 @@
+-- Table: public.order_product
+
+ALTER TABLE public.order_product
+    ADD COLUMN IF NOT EXISTS unit_price double precision NOT NULL DEFAULT 0,
+    ADD CONSTRAINT order_product_quantity_check CHECK (quantity > 0);
+
+-- Lines placed before prices were copied get the current product price, the closest known value
+UPDATE public.order_product
+SET unit_price = products.price
+FROM public.products
+WHERE products.id = order_product.product_id
+    AND products.price IS NOT NULL;
+
+-- New lines must always be given their price
+ALTER TABLE public.order_product
+    ALTER COLUMN unit_price DROP DEFAULT;
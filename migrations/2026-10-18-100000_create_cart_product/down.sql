@@ -0,0 +1,3 @@
+-- Table: public.cart_product
+
+DROP TABLE IF EXISTS public.cart_product;
@@ -0,0 +1,22 @@
+-- Table: public.cart_product
+
+CREATE TABLE IF NOT EXISTS public.cart_product
+(
+    user_id uuid NOT NULL,
+    product_id uuid NOT NULL,
+    created_at timestamp(0) without time zone DEFAULT CURRENT_TIMESTAMP,
+    updated_at timestamp(0) without time zone DEFAULT CURRENT_TIMESTAMP,
+    quantity integer NOT NULL,
+    CONSTRAINT pk_cart_product PRIMARY KEY (user_id, product_id),
+    CONSTRAINT cart_product_user_id_foreign FOREIGN KEY (user_id)
+        REFERENCES public.users (id) MATCH SIMPLE
+        ON UPDATE NO ACTION
+        ON DELETE CASCADE,
+    CONSTRAINT cart_product_product_id_foreign FOREIGN KEY (product_id)
+        REFERENCES public.products (id) MATCH SIMPLE
+        ON UPDATE NO ACTION
+        ON DELETE CASCADE,
+    CONSTRAINT cart_product_quantity_check CHECK (quantity > 0)
+);
+
+SELECT diesel_manage_updated_at('cart_product');
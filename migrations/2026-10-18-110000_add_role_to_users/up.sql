@@ -0,0 +1,5 @@
+-- Table: public.users
+
+ALTER TABLE public.users
+    ADD COLUMN IF NOT EXISTS role text NOT NULL DEFAULT 'CUSTOMER'::text,
+    ADD CONSTRAINT users_role_check CHECK (role::text = ANY (ARRAY['CUSTOMER'::text, 'STAFF'::text, 'ADMIN'::text]::text[]));
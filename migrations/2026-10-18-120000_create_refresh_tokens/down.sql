@@ -0,0 +1,3 @@
+-- Table: public.refresh_tokens
+
+DROP TABLE IF EXISTS public.refresh_tokens;
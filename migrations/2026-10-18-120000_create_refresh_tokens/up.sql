@@ -0,0 +1,27 @@
+-- Table: public.refresh_tokens
+
+CREATE TABLE IF NOT EXISTS public.refresh_tokens
+(
+    id uuid NOT NULL DEFAULT gen_random_uuid(),
+    created_at timestamp(0) without time zone DEFAULT CURRENT_TIMESTAMP,
+    updated_at timestamp(0) without time zone DEFAULT CURRENT_TIMESTAMP,
+    user_id uuid NOT NULL,
+    family_id uuid NOT NULL,
+    token_hash text NOT NULL,
+    expires_at timestamp(0) without time zone NOT NULL,
+    used_at timestamp(0) without time zone,
+    revoked_at timestamp(0) without time zone,
+    CONSTRAINT refresh_tokens_pkey PRIMARY KEY (id),
+    CONSTRAINT refresh_tokens_token_hash_unique UNIQUE (token_hash),
+    CONSTRAINT refresh_tokens_user_id_foreign FOREIGN KEY (user_id)
+        REFERENCES public.users (id) MATCH SIMPLE
+        ON UPDATE NO ACTION
+        ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS refresh_tokens_family_id_index
+    ON public.refresh_tokens USING btree
+    (family_id ASC NULLS LAST)
+    TABLESPACE pg_default;
+
+SELECT diesel_manage_updated_at('refresh_tokens');
@@ -0,0 +1,8 @@
+-- Table: public.refresh_tokens
+
+ALTER TABLE public.refresh_tokens
+    DROP COLUMN IF EXISTS access_token_jti;
+
+-- Table: public.revoked_access_tokens
+
+DROP TABLE IF EXISTS public.revoked_access_tokens;
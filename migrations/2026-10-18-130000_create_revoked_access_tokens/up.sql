@@ -0,0 +1,14 @@
+-- Table: public.revoked_access_tokens
+
+CREATE TABLE IF NOT EXISTS public.revoked_access_tokens
+(
+    jti uuid NOT NULL,
+    created_at timestamp(0) without time zone DEFAULT CURRENT_TIMESTAMP,
+    expires_at timestamp(0) without time zone NOT NULL,
+    CONSTRAINT revoked_access_tokens_pkey PRIMARY KEY (jti)
+);
+
+-- Table: public.refresh_tokens
+
+ALTER TABLE public.refresh_tokens
+    ADD COLUMN IF NOT EXISTS access_token_jti uuid;
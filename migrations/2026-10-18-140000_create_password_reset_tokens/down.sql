@@ -0,0 +1,3 @@
+-- Table: public.password_reset_tokens
+
+DROP TABLE IF EXISTS public.password_reset_tokens;
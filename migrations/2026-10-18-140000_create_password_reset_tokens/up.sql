@@ -0,0 +1,25 @@
+-- Table: public.password_reset_tokens
+
+CREATE TABLE IF NOT EXISTS public.password_reset_tokens
+(
+    id uuid NOT NULL DEFAULT gen_random_uuid(),
+    created_at timestamp(0) without time zone DEFAULT CURRENT_TIMESTAMP,
+    updated_at timestamp(0) without time zone DEFAULT CURRENT_TIMESTAMP,
+    user_id uuid NOT NULL,
+    token_hash text NOT NULL,
+    expires_at timestamp(0) without time zone NOT NULL,
+    used_at timestamp(0) without time zone,
+    CONSTRAINT password_reset_tokens_pkey PRIMARY KEY (id),
+    CONSTRAINT password_reset_tokens_token_hash_unique UNIQUE (token_hash),
+    CONSTRAINT password_reset_tokens_user_id_foreign FOREIGN KEY (user_id)
+        REFERENCES public.users (id) MATCH SIMPLE
+        ON UPDATE NO ACTION
+        ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS password_reset_tokens_user_id_index
+    ON public.password_reset_tokens USING btree
+    (user_id ASC NULLS LAST)
+    TABLESPACE pg_default;
+
+SELECT diesel_manage_updated_at('password_reset_tokens');
@@ -0,0 +1,14 @@
+-- Table: public.users
+
+-- Reverting always leaves the column named as create_users defines it, including on
+-- databases that had already been fixed by hand before this migration ran.
+DO $$
+BEGIN
+    IF EXISTS (
+        SELECT 1 FROM information_schema.columns
+        WHERE table_schema = 'public' AND table_name = 'users' AND column_name = 'password'
+    ) THEN
+        ALTER TABLE public.users RENAME COLUMN password TO password_hash;
+    END IF;
+END
+$$;
@@ -0,0 +1,14 @@
+-- Table: public.users
+
+-- The create_users migration named the column password_hash while the application
+-- has always used password. Databases already fixed by hand are left untouched.
+DO $$
+BEGIN
+    IF EXISTS (
+        SELECT 1 FROM information_schema.columns
+        WHERE table_schema = 'public' AND table_name = 'users' AND column_name = 'password_hash'
+    ) THEN
+        ALTER TABLE public.users RENAME COLUMN password_hash TO password;
+    END IF;
+END
+$$;
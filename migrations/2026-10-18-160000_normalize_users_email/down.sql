@@ -0,0 +1,4 @@
+-- Table: public.users
+
+-- Emails stay normalized, their original case is lost.
+DROP INDEX IF EXISTS public.users_email_lower_unique;
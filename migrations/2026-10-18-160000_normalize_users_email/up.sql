@@ -0,0 +1,19 @@
+-- Table: public.users
+
+-- Emails used to be stored as typed, while sign-in looks them up lowercased. Accounts
+-- differing only by case can't be merged automatically, they must be fixed by hand first.
+DO $$
+BEGIN
+    IF EXISTS (
+        SELECT 1 FROM public.users
+        GROUP BY lower(trim(email))
+        HAVING count(*) > 1
+    ) THEN
+        RAISE EXCEPTION 'public.users has emails differing only by case, merge these accounts before migrating';
+    END IF;
+END
+$$;
+
+UPDATE public.users SET email = lower(trim(email)) WHERE email <> lower(trim(email));
+
+CREATE UNIQUE INDEX IF NOT EXISTS users_email_lower_unique ON public.users (lower(email));
@@ -0,0 +1,564 @@
+use actix_web::http::header::HeaderName;
+use actix_web::http::{Method, Uri};
+use chrono::Duration;
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+/// JWT secrets shorter than this are refused, HS256 keys should be at least 256 bits.
+const MIN_JWT_SECRET_LENGTH: usize = 32;
+
+const DEFAULT_MOLLIE_API_URL: &str = "https://api.mollie.com/v2";
+
+/// Locales allowed by the `*_translations_locale_check` constraints of the database.
+const TRANSLATABLE_LOCALES: [&str; 3] = ["en", "fr", "zh"];
+
+/// Settings of the application, loaded once at startup.
+///
+/// Every setting is an environment variable. When `CONFIG_FILE` points to a TOML file,
+/// settings missing from the environment are read from it, keyed by the variable name
+/// in lowercase, e.g. `jwt_secret = "..."` or `cors_allowed_origins = ["https://..."]`.
+pub struct Config {
+    pub database: DatabaseConfig,
+    pub api_url: String,
+    pub jwt_secret: String,
+    pub tokens: TokenConfig,
+    pub cors: CorsConfig,
+    /// Locales the menu is translated into, every product and category needs all of them.
+    pub supported_locales: Vec<String>,
+    pub default_locale: String,
+    pub mollie: MollieConfig,
+    pub mailer: MailerTransport,
+    /// Links sent by email, `{token}` is replaced with the token. The bare token is sent when unset.
+    pub verify_email_url: Option<String>,
+    pub reset_password_url: Option<String>,
+    /// Only let users with a verified email address place orders.
+    pub require_verified_email: bool,
+    pub password: PasswordConfig,
+    pub login: LoginConfig,
+    /// Reverse proxies whose `X-Forwarded-For` header is trusted to tell the client address.
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+pub struct DatabaseConfig {
+    pub url: String,
+    pub pool_size: u32,
+}
+
+pub struct TokenConfig {
+    pub access_token_lifetime: Duration,
+    pub refresh_token_lifetime: Duration,
+    pub email_verification_token_lifetime: Duration,
+    pub password_reset_token_lifetime: Duration,
+}
+
+/// Cross-origin policies: the public menu can be read from anywhere, the rest of the
+/// API from the configured origins, and the admin scopes only from the admin origins.
+pub struct CorsConfig {
+    /// Origins allowed to read the public menu, `*` allows any origin.
+    pub public_allowed_origins: Vec<String>,
+    /// Origins allowed on the rest of the API, `*` allows any origin.
+    pub allowed_origins: Vec<String>,
+    /// Origins allowed on the admin scopes, none by default.
+    pub admin_allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<Method>,
+    pub allowed_headers: Vec<HeaderName>,
+    pub allow_credentials: bool,
+    pub max_age: usize,
+}
+
+pub struct MollieConfig {
+    pub api_url: String,
+    pub api_key: String,
+    pub redirect_url: String,
+    pub webhook_url: Option<String>,
+    pub timeout: std::time::Duration,
+}
+
+/// Where emails go, chosen with `MAILER_TRANSPORT`. Both transports keep emails local,
+/// tokens included, so the choice is explicit rather than a silent stdout default.
+pub enum MailerTransport {
+    /// Appended to `MAILER_FILE`.
+    File(PathBuf),
+    /// Printed with the server logs, for local development only.
+    Stdout,
+}
+
+pub struct PasswordConfig {
+    pub min_length: usize,
+    pub max_length: usize,
+    /// Refused passwords, one per line, added to the bundled list.
+    pub breached_passwords_file: Option<PathBuf>,
+}
+
+pub struct LoginConfig {
+    /// Failed sign-ins before an account is locked.
+    pub lock_after: u32,
+    /// Failed sign-ins before a client IP is locked.
+    pub ip_lock_after: u32,
+    pub lock_duration: Duration,
+}
+
+/// Every problem found while loading the configuration.
+#[derive(Debug)]
+pub struct ConfigError(pub Vec<String>);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid configuration:")?;
+        for problem in &self.0 {
+            write!(f, "\n  - {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+impl Config {
+    pub fn load() -> Result<Config, ConfigError> {
+        let mut settings = Settings::load();
+
+        let database = DatabaseConfig::read(&mut settings);
+        let api_url = settings.required("API_URL");
+        let jwt_secret = settings.required("JWT_SECRET");
+        if !jwt_secret.is_empty() && jwt_secret.len() < MIN_JWT_SECRET_LENGTH {
+            settings.invalid(format!("JWT_SECRET must be at least {} characters long", MIN_JWT_SECRET_LENGTH));
+        }
+
+        let tokens = TokenConfig {
+            access_token_lifetime: Duration::minutes(settings.positive("ACCESS_TOKEN_MINUTES", 15)),
+            refresh_token_lifetime: Duration::days(settings.positive("REFRESH_TOKEN_DAYS", 7)),
+            email_verification_token_lifetime: Duration::hours(settings.positive("EMAIL_VERIFICATION_TOKEN_HOURS", 24)),
+            password_reset_token_lifetime: Duration::minutes(settings.positive("PASSWORD_RESET_TOKEN_MINUTES", 60)),
+        };
+
+        let cors = CorsConfig::read(&mut settings);
+        let supported_locales = settings.list("SUPPORTED_LOCALES", &["fr", "en", "zh"]);
+        for locale in supported_locales.iter().filter(|locale| !TRANSLATABLE_LOCALES.contains(&locale.as_str())) {
+            settings.invalid(format!("SUPPORTED_LOCALES contains {}, translations can only be stored in {}", locale, TRANSLATABLE_LOCALES.join(", ")));
+        }
+        let default_locale = settings.optional("DEFAULT_LOCALE").unwrap_or_else(|| "en".to_string());
+        if !supported_locales.contains(&default_locale) {
+            settings.invalid(format!("DEFAULT_LOCALE {} must be one of SUPPORTED_LOCALES", default_locale));
+        }
+
+        let mollie = MollieConfig {
+            api_url: settings.optional("MOLLIE_API_URL").unwrap_or_else(|| DEFAULT_MOLLIE_API_URL.to_string()),
+            api_key: settings.required("MOLLIE_API_KEY"),
+            redirect_url: settings.required("MOLLIE_REDIRECT_URL"),
+            webhook_url: settings.optional("MOLLIE_WEBHOOK_URL"),
+            timeout: std::time::Duration::from_secs(settings.positive("MOLLIE_TIMEOUT_SECONDS", 10)),
+        };
+
+        let password = PasswordConfig {
+            min_length: settings.positive("PASSWORD_MIN_LENGTH", 8),
+            max_length: settings.positive("PASSWORD_MAX_LENGTH", 128),
+            breached_passwords_file: settings.optional("BREACHED_PASSWORDS_FILE").map(PathBuf::from),
+        };
+        if password.min_length > password.max_length {
+            settings.invalid("PASSWORD_MIN_LENGTH must not be greater than PASSWORD_MAX_LENGTH".to_string());
+        }
+
+        let login = LoginConfig {
+            lock_after: settings.positive("LOGIN_LOCK_AFTER", 10),
+            ip_lock_after: settings.positive("LOGIN_IP_LOCK_AFTER", 100),
+            lock_duration: Duration::minutes(settings.positive("LOGIN_LOCK_MINUTES", 15)),
+        };
+
+        let trusted_proxies = settings
+            .list("TRUSTED_PROXIES", &[])
+            .into_iter()
+            .filter_map(|proxy| match proxy.parse::<IpAddr>() {
+                Ok(proxy) => Some(proxy),
+                Err(_) => {
+                    settings.invalid(format!("TRUSTED_PROXIES contains an invalid IP address {}", proxy));
+                    None
+                },
+            })
+            .collect();
+
+        let config = Config {
+            database,
+            api_url,
+            jwt_secret,
+            tokens,
+            cors,
+            supported_locales,
+            default_locale,
+            mollie,
+            mailer: MailerTransport::read(&mut settings),
+            verify_email_url: settings.optional("VERIFY_EMAIL_URL"),
+            reset_password_url: settings.optional("RESET_PASSWORD_URL"),
+            require_verified_email: settings.flag("REQUIRE_VERIFIED_EMAIL", false),
+            password,
+            login,
+            trusted_proxies,
+        };
+        settings.finish(config)
+    }
+
+    /// Makes the configuration available through [`Config::global`].
+    pub fn init(config: Config) -> &'static Config {
+        CONFIG.get_or_init(|| config)
+    }
+
+    /// The configuration of the application, which must have been initialized at startup.
+    pub fn global() -> &'static Config {
+        CONFIG.get().expect("Configuration not initialized")
+    }
+}
+
+impl DatabaseConfig {
+    /// Loads only the database settings, for commands that don't start the server.
+    pub fn load() -> Result<DatabaseConfig, ConfigError> {
+        let mut settings = Settings::load();
+        let database = DatabaseConfig::read(&mut settings);
+        settings.finish(database)
+    }
+
+    fn read(settings: &mut Settings) -> DatabaseConfig {
+        DatabaseConfig {
+            url: settings.required("DATABASE_URL"),
+            pool_size: settings.positive("DATABASE_POOL_SIZE", 10),
+        }
+    }
+}
+
+impl MailerTransport {
+    fn read(settings: &mut Settings) -> MailerTransport {
+        match settings.required("MAILER_TRANSPORT").trim() {
+            "file" => match settings.optional("MAILER_FILE") {
+                Some(path) => MailerTransport::File(PathBuf::from(path)),
+                None => {
+                    settings.invalid("MAILER_FILE must be set when MAILER_TRANSPORT is file".to_string());
+                    MailerTransport::Stdout
+                },
+            },
+            "stdout" => MailerTransport::Stdout,
+            // Already reported as missing
+            "" => MailerTransport::Stdout,
+            transport => {
+                settings.invalid(format!("MAILER_TRANSPORT must be file or stdout, got {}", transport));
+                MailerTransport::Stdout
+            },
+        }
+    }
+}
+
+impl CorsConfig {
+    fn read(settings: &mut Settings) -> CorsConfig {
+        let allowed_methods = settings
+            .list("CORS_ALLOWED_METHODS", &["GET", "POST", "PUT", "DELETE", "OPTIONS", "HEAD"])
+            .into_iter()
+            .filter_map(|method| match Method::from_bytes(method.to_uppercase().as_bytes()) {
+                Ok(method) => Some(method),
+                Err(_) => {
+                    settings.invalid(format!("CORS_ALLOWED_METHODS contains an invalid method {}", method));
+                    None
+                },
+            })
+            .collect();
+        let allowed_headers = settings
+            .list("CORS_ALLOWED_HEADERS", &["Authorization", "Accept", "Content-Type"])
+            .into_iter()
+            .filter_map(|header| match HeaderName::from_bytes(header.as_bytes()) {
+                Ok(header) => Some(header),
+                Err(_) => {
+                    settings.invalid(format!("CORS_ALLOWED_HEADERS contains an invalid header {}", header));
+                    None
+                },
+            })
+            .collect();
+
+        let cors = CorsConfig {
+            public_allowed_origins: CorsConfig::origins(settings, "PUBLIC_CORS_ALLOWED_ORIGINS", &["*"]),
+            allowed_origins: CorsConfig::origins(settings, "CORS_ALLOWED_ORIGINS", &["*"]),
+            admin_allowed_origins: CorsConfig::origins(settings, "ADMIN_CORS_ALLOWED_ORIGINS", &[]),
+            allowed_methods,
+            allowed_headers,
+            allow_credentials: settings.flag("CORS_ALLOW_CREDENTIALS", false),
+            max_age: settings.positive("CORS_MAX_AGE", 3600),
+        };
+
+        if cors.admin_allowed_origins.iter().any(|origin| origin == "*") {
+            settings.invalid("ADMIN_CORS_ALLOWED_ORIGINS must list origins, * is not allowed".to_string());
+        }
+        // Browsers refuse credentials with a wildcard origin
+        if cors.allow_credentials && cors.allowed_origins.iter().any(|origin| origin == "*") {
+            settings.invalid("CORS_ALLOW_CREDENTIALS requires CORS_ALLOWED_ORIGINS to list origins instead of *".to_string());
+        }
+        cors
+    }
+
+    /// Reads a list of origins, either `*` alone or origins like `https://shop.example.com`.
+    fn origins(settings: &mut Settings, name: &str, default: &[&str]) -> Vec<String> {
+        let origins = settings.list(name, default);
+        if origins.iter().any(|origin| origin == "*") {
+            if origins.len() > 1 {
+                settings.invalid(format!("{} must be either * or a list of origins, not both", name));
+            }
+            return origins;
+        }
+        for origin in &origins {
+            let valid = origin
+                .parse::<Uri>()
+                .is_ok_and(|uri| uri.scheme().is_some() && uri.authority().is_some() && matches!(uri.path(), "" | "/") && uri.query().is_none());
+            if !valid || origin.ends_with('/') {
+                settings.invalid(format!("{} contains an invalid origin {}", name, origin));
+            }
+        }
+        origins
+    }
+}
+
+/// Raw settings from the environment and the optional file, collecting problems as they are read.
+struct Settings {
+    file: HashMap<String, String>,
+    problems: Vec<String>,
+}
+
+impl Settings {
+    fn load() -> Settings {
+        let mut settings = Settings { file: HashMap::new(), problems: Vec::new() };
+        if let Some(path) = env::var("CONFIG_FILE").ok().filter(|path| !path.is_empty()) {
+            match fs::read_to_string(&path).map_err(|e| e.to_string()).and_then(|content| content.parse::<toml::Table>().map_err(|e| e.to_string())) {
+                Ok(table) => settings.read_table(table),
+                Err(e) => settings.invalid(format!("CONFIG_FILE {} can't be read: {}", path, e)),
+            }
+        }
+        settings
+    }
+
+    fn read_table(&mut self, table: toml::Table) {
+        for (key, value) in table {
+            let value = match value {
+                toml::Value::String(value) => value,
+                toml::Value::Integer(value) => value.to_string(),
+                toml::Value::Boolean(value) => value.to_string(),
+                toml::Value::Array(values) => {
+                    let items: Option<Vec<String>> = values
+                        .into_iter()
+                        .map(|value| value.as_str().map(str::to_string))
+                        .collect();
+                    match items {
+                        Some(items) => items.join(","),
+                        None => {
+                            self.invalid(format!("{} must be a list of strings in CONFIG_FILE", key));
+                            continue;
+                        },
+                    }
+                },
+                _ => {
+                    self.invalid(format!("{} has an unsupported type in CONFIG_FILE", key));
+                    continue;
+                },
+            };
+            self.file.insert(key.to_uppercase(), value);
+        }
+    }
+
+    fn invalid(&mut self, problem: String) {
+        self.problems.push(problem);
+    }
+
+    /// The value of a setting, empty values counting as unset.
+    fn optional(&self, name: &str) -> Option<String> {
+        env::var(name)
+            .ok()
+            .or_else(|| self.file.get(name).cloned())
+            .filter(|value| !value.trim().is_empty())
+    }
+
+    fn required(&mut self, name: &str) -> String {
+        self.optional(name).unwrap_or_else(|| {
+            self.invalid(format!("{} must be set", name));
+            String::new()
+        })
+    }
+
+    fn parse<T: FromStr>(&mut self, name: &str, default: T, expected: &str) -> T {
+        match self.optional(name) {
+            Some(value) => value.trim().parse().unwrap_or_else(|_| {
+                self.invalid(format!("{} must be {}, got {}", name, expected, value));
+                default
+            }),
+            None => default,
+        }
+    }
+
+    fn positive<T: FromStr + Default + PartialOrd + Copy>(&mut self, name: &str, default: T) -> T {
+        let value = self.parse(name, default, "a positive number");
+        if value <= T::default() {
+            self.invalid(format!("{} must be a positive number", name));
+            return default;
+        }
+        value
+    }
+
+    fn flag(&mut self, name: &str, default: bool) -> bool {
+        match self.optional(name).as_deref().map(str::trim) {
+            Some("true") | Some("1") => true,
+            Some("false") | Some("0") => false,
+            Some(value) => {
+                self.invalid(format!("{} must be true or false, got {}", name, value));
+                default
+            },
+            None => default,
+        }
+    }
+
+    /// A comma separated list.
+    fn list(&self, name: &str, default: &[&str]) -> Vec<String> {
+        match self.optional(name) {
+            Some(value) => value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(str::to_string)
+                .collect(),
+            None => default.iter().map(|item| item.to_string()).collect(),
+        }
+    }
+
+    fn finish<T>(self, value: T) -> Result<T, ConfigError> {
+        if self.problems.is_empty() {
+            Ok(value)
+        } else {
+            Err(ConfigError(self.problems))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(file: &[(&str, &str)]) -> Settings {
+        let file = file.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect();
+        Settings { file, problems: Vec::new() }
+    }
+
+    #[test]
+    fn lists_are_split_on_commas() {
+        let settings = settings(&[("TEST_LIST", " a, b ,,c "), ("TEST_EMPTY_LIST", " ")]);
+
+        assert_eq!(settings.list("TEST_LIST", &["default"]), vec!["a", "b", "c"]);
+        assert_eq!(settings.list("TEST_EMPTY_LIST", &["default"]), vec!["default"]);
+        assert_eq!(settings.list("TEST_MISSING_LIST", &["default"]), vec!["default"]);
+    }
+
+    #[test]
+    fn positive_numbers_fall_back_to_the_default() {
+        let mut settings = settings(&[("TEST_POSITIVE", " 42 "), ("TEST_ZERO", "0"), ("TEST_NOT_A_NUMBER", "ten")]);
+
+        assert_eq!(settings.positive("TEST_POSITIVE", 1), 42);
+        assert_eq!(settings.positive("TEST_MISSING_NUMBER", 7), 7);
+        assert!(settings.problems.is_empty());
+
+        assert_eq!(settings.positive("TEST_ZERO", 7), 7);
+        assert_eq!(settings.positive("TEST_NOT_A_NUMBER", 7u32), 7);
+        assert_eq!(settings.problems, vec![
+            "TEST_ZERO must be a positive number",
+            "TEST_NOT_A_NUMBER must be a positive number, got ten",
+        ]);
+    }
+
+    #[test]
+    fn flags_accept_booleans_and_digits() {
+        let mut settings = settings(&[("TEST_TRUE", "true"), ("TEST_ONE", "1"), ("TEST_FALSE", "false"), ("TEST_YES", "yes")]);
+
+        assert!(settings.flag("TEST_TRUE", false));
+        assert!(settings.flag("TEST_ONE", false));
+        assert!(!settings.flag("TEST_FALSE", true));
+        assert!(settings.flag("TEST_MISSING_FLAG", true));
+        assert!(settings.problems.is_empty());
+
+        assert!(!settings.flag("TEST_YES", false));
+        assert_eq!(settings.problems, vec!["TEST_YES must be true or false, got yes"]);
+    }
+
+    #[test]
+    fn config_files_are_read_as_uppercase_settings() {
+        let table = r#"
+            test_string = "value"
+            test_integer = 5
+            test_boolean = true
+            test_array = ["https://a.example.com", "https://b.example.com"]
+            test_mixed_array = ["a", 1]
+            test_float = 1.5
+        "#.parse::<toml::Table>().unwrap();
+        let mut settings = settings(&[]);
+        settings.read_table(table);
+
+        assert_eq!(settings.optional("TEST_STRING").as_deref(), Some("value"));
+        assert_eq!(settings.positive("TEST_INTEGER", 1), 5);
+        assert!(settings.flag("TEST_BOOLEAN", false));
+        assert_eq!(settings.list("TEST_ARRAY", &[]), vec!["https://a.example.com", "https://b.example.com"]);
+        assert_eq!(settings.optional("TEST_MIXED_ARRAY"), None);
+        assert_eq!(settings.optional("TEST_FLOAT"), None);
+        assert_eq!(settings.problems, vec![
+            "test_float has an unsupported type in CONFIG_FILE",
+            "test_mixed_array must be a list of strings in CONFIG_FILE",
+        ]);
+    }
+
+    #[test]
+    fn the_environment_overrides_the_config_file() {
+        // Only this test sets the variable, so it can't leak into the others
+        env::set_var("TEST_OVERRIDDEN_SETTING", "from the environment");
+        let settings = settings(&[("TEST_OVERRIDDEN_SETTING", "from the file")]);
+
+        assert_eq!(settings.optional("TEST_OVERRIDDEN_SETTING").as_deref(), Some("from the environment"));
+    }
+
+    #[test]
+    fn every_problem_is_reported() {
+        let mut invalid = settings(&[("TEST_SIZE", "-1"), ("TEST_ENABLED", "maybe")]);
+        invalid.required("TEST_MISSING_SECRET");
+        invalid.positive("TEST_SIZE", 10i32);
+        invalid.flag("TEST_ENABLED", false);
+
+        let ConfigError(problems) = invalid.finish(()).unwrap_err();
+        assert_eq!(problems, vec![
+            "TEST_MISSING_SECRET must be set",
+            "TEST_SIZE must be a positive number",
+            "TEST_ENABLED must be true or false, got maybe",
+        ]);
+        assert!(settings(&[]).finish(()).is_ok());
+    }
+
+    #[test]
+    fn origins_are_either_a_wildcard_or_valid_origins() {
+        let mut settings = settings(&[
+            ("TEST_ANY_ORIGIN", "*"),
+            ("TEST_ORIGINS", "https://shop.example.com, http://localhost:3000"),
+            ("TEST_MIXED_ORIGINS", "https://shop.example.com,*"),
+            ("TEST_INVALID_ORIGINS", "shop.example.com,https://shop.example.com/,https://shop.example.com/menu,https://"),
+        ]);
+
+        assert_eq!(CorsConfig::origins(&mut settings, "TEST_ANY_ORIGIN", &[]), vec!["*"]);
+        assert_eq!(
+            CorsConfig::origins(&mut settings, "TEST_ORIGINS", &[]),
+            vec!["https://shop.example.com", "http://localhost:3000"]
+        );
+        assert!(settings.problems.is_empty());
+
+        CorsConfig::origins(&mut settings, "TEST_MIXED_ORIGINS", &[]);
+        CorsConfig::origins(&mut settings, "TEST_INVALID_ORIGINS", &[]);
+        assert_eq!(settings.problems, vec![
+            "TEST_MIXED_ORIGINS must be either * or a list of origins, not both",
+            "TEST_INVALID_ORIGINS contains an invalid origin shop.example.com",
+            "TEST_INVALID_ORIGINS contains an invalid origin https://shop.example.com/",
+            "TEST_INVALID_ORIGINS contains an invalid origin https://shop.example.com/menu",
+            "TEST_INVALID_ORIGINS contains an invalid origin https://",
+        ]);
+    }
+}
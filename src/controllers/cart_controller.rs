@@ -0,0 +1,92 @@
+use crate::controllers::order_controller::{created_order_response, ensure_can_order};
+use crate::errors::AppError;
+use crate::locale::preferred_locale;
+use crate::middlewares::auth_user::AuthUser;
+use crate::models::cart::{Cart, CartProductForm, CartQuantityForm, CheckoutForm};
+use crate::payments::PaymentClient;
+use crate::repository::Repository;
+use crate::validation::Validated;
+use actix_web::{web, HttpRequest, HttpResponse};
+use uuid::Uuid;
+
+async fn cart_response(req: &HttpRequest, repository: &Repository, user_id: Uuid) -> Result<HttpResponse, AppError> {
+    let locale = preferred_locale(req);
+    let cart = repository.run(move |conn| Cart::find_for_user(conn, user_id, &locale)).await?;
+    Ok(HttpResponse::Ok().json(cart))
+}
+
+fn product_not_in_cart() -> AppError {
+    AppError::not_found("product_not_in_cart", "Product not in cart")
+}
+
+pub async fn get_cart(req: HttpRequest, auth: AuthUser, repository: web::Data<Repository>) -> Result<HttpResponse, AppError> {
+    let user_id = auth.user(&repository).await?.id;
+    cart_response(&req, &repository, user_id).await
+}
+
+pub async fn add_product(req: HttpRequest, auth: AuthUser, repository: web::Data<Repository>, form: Validated<CartProductForm>) -> Result<HttpResponse, AppError> {
+    let user_id = auth.user(&repository).await?.id;
+    let form = form.into_inner();
+    repository.run(move |conn| Cart::add_product(conn, user_id, &form)).await?;
+    cart_response(&req, &repository, user_id).await
+}
+
+pub async fn update_product(
+    req: HttpRequest,
+    auth: AuthUser,
+    repository: web::Data<Repository>,
+    product_id: web::Path<Uuid>,
+    form: Validated<CartQuantityForm>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = auth.user(&repository).await?.id;
+    let product_id = product_id.into_inner();
+    let quantity = form.quantity;
+
+    if !repository.run(move |conn| Cart::update_product(conn, user_id, product_id, quantity)).await? {
+        return Err(product_not_in_cart());
+    }
+    cart_response(&req, &repository, user_id).await
+}
+
+pub async fn remove_product(req: HttpRequest, auth: AuthUser, repository: web::Data<Repository>, product_id: web::Path<Uuid>) -> Result<HttpResponse, AppError> {
+    let user_id = auth.user(&repository).await?.id;
+    let product_id = product_id.into_inner();
+
+    if !repository.run(move |conn| Cart::remove_product(conn, user_id, product_id)).await? {
+        return Err(product_not_in_cart());
+    }
+    cart_response(&req, &repository, user_id).await
+}
+
+pub async fn clear_cart(auth: AuthUser, repository: web::Data<Repository>) -> Result<HttpResponse, AppError> {
+    let user_id = auth.user(&repository).await?.id;
+    repository.run(move |conn| Cart::clear(conn, user_id)).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub async fn checkout(
+    req: HttpRequest,
+    auth: AuthUser,
+    repository: web::Data<Repository>,
+    payment_client: web::Data<dyn PaymentClient>,
+    form: Validated<CheckoutForm>,
+) -> Result<HttpResponse, AppError> {
+    let user = auth.user(&repository).await?;
+    ensure_can_order(user)?;
+
+    let user_id = user.id;
+    let payment_mode = form.payment_mode;
+    let checkout = repository.run(move |conn| Cart::checkout(conn, user_id, payment_mode)).await?;
+
+    match created_order_response(&req, &repository, payment_client.get_ref(), user_id, checkout.order.id).await {
+        // The payment couldn't be created and the order is failed, its lines go back to the cart
+        Err(e @ AppError::Upstream { .. }) => {
+            let lines = checkout.lines;
+            if let Err(restore_error) = repository.run(move |conn| Cart::restore(conn, user_id, &lines)).await {
+                log::error!("Cart of user {} not restored after order {} failed: {}", user_id, checkout.order.id, restore_error);
+            }
+            Err(e)
+        },
+        response => response,
+    }
+}
@@ -0,0 +1,36 @@
+use crate::errors::AppError;
+use crate::models::product_category::{NewCategoryForm, ProductCategory, ReorderCategoriesForm, UpdateCategoryForm};
+use crate::repository::Repository;
+use crate::validation::Validated;
+use actix_web::{web, HttpResponse};
+use uuid::Uuid;
+
+pub async fn get_categories(repository: web::Data<Repository>) -> Result<HttpResponse, AppError> {
+    let categories = repository.run(ProductCategory::find_all_details).await?;
+    Ok(HttpResponse::Ok().json(categories))
+}
+
+pub async fn create_category(repository: web::Data<Repository>, form: Validated<NewCategoryForm>) -> Result<HttpResponse, AppError> {
+    let form = form.into_inner();
+    let category = repository.run(move |conn| ProductCategory::create(conn, &form)).await?;
+    Ok(HttpResponse::Created().json(category))
+}
+
+pub async fn update_category(repository: web::Data<Repository>, category_id: web::Path<Uuid>, form: Validated<UpdateCategoryForm>) -> Result<HttpResponse, AppError> {
+    let category_id = category_id.into_inner();
+    let form = form.into_inner();
+    let category = repository.run(move |conn| ProductCategory::update(conn, category_id, &form)).await?;
+    Ok(HttpResponse::Ok().json(category))
+}
+
+pub async fn reorder_categories(repository: web::Data<Repository>, form: Validated<ReorderCategoriesForm>) -> Result<HttpResponse, AppError> {
+    let category_ids = form.into_inner().category_ids;
+    let categories = repository.run(move |conn| ProductCategory::reorder(conn, &category_ids)).await?;
+    Ok(HttpResponse::Ok().json(categories))
+}
+
+pub async fn delete_category(repository: web::Data<Repository>, category_id: web::Path<Uuid>) -> Result<HttpResponse, AppError> {
+    let category_id = category_id.into_inner();
+    repository.run(move |conn| ProductCategory::delete(conn, category_id)).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -1,3 +1,6 @@
-//pub mod order_controller;
+pub mod cart_controller;
+pub mod category_controller;
+pub mod order_controller;
 pub mod product_controller;
 pub mod user_controller;
+pub mod webhook_controller;
\ No newline at end of file
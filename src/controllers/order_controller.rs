@@ -0,0 +1,100 @@
+use crate::config::Config;
+use crate::errors::AppError;
+use crate::locale::preferred_locale;
+use crate::middlewares::auth_user::AuthUser;
+use crate::models::order::{Order, OrderForm, OrderWithProducts};
+use crate::models::order_status::OrderStatus;
+use crate::models::payment_mode::PaymentMode;
+use crate::models::user::User;
+use crate::payments::{PaymentClient, PaymentRequest};
+use crate::repository::Repository;
+use crate::validation::Validated;
+use actix_web::{web, HttpRequest, HttpResponse};
+use uuid::Uuid;
+
+/// Ordering can be restricted to users who verified their email address, with `REQUIRE_VERIFIED_EMAIL`.
+pub(crate) fn ensure_can_order(user: &User) -> Result<(), AppError> {
+    if Config::global().require_verified_email && !user.is_email_verified() {
+        return Err(AppError::forbidden("email_not_verified", "Email address must be verified to order"));
+    }
+    Ok(())
+}
+
+pub async fn place_order(
+    req: HttpRequest,
+    auth: AuthUser,
+    repository: web::Data<Repository>,
+    payment_client: web::Data<dyn PaymentClient>,
+    order_form: Validated<OrderForm>,
+) -> Result<HttpResponse, AppError> {
+    let user = auth.user(&repository).await?;
+    ensure_can_order(user)?;
+
+    let user_id = user.id;
+    let order_form = order_form.into_inner();
+    let order = repository.run(move |conn| Order::place(conn, user_id, &order_form)).await?;
+
+    created_order_response(&req, &repository, payment_client.get_ref(), user_id, order.id).await
+}
+
+fn order_not_found() -> AppError {
+    AppError::not_found("order_not_found", "Order not found")
+}
+
+/// Responds with a freshly placed order, creating its Mollie payment first when it's paid online.
+/// When the payment can't be created, the order is marked FAILED and `AppError::Upstream` is returned.
+pub(crate) async fn created_order_response(
+    req: &HttpRequest,
+    repository: &Repository,
+    payment_client: &dyn PaymentClient,
+    user_id: Uuid,
+    order_id: Uuid,
+) -> Result<HttpResponse, AppError> {
+    let locale = preferred_locale(req);
+    let placed_order = repository
+        .run(move |conn| Order::find_for_user(conn, user_id, order_id, &locale))
+        .await?
+        .ok_or_else(order_not_found)?;
+
+    if placed_order.order.payment_mode != PaymentMode::Online {
+        return Ok(HttpResponse::Created().json(placed_order));
+    }
+
+    // Online orders are paid through a Mollie checkout the client gets redirected to
+    let payment_request = PaymentRequest {
+        order_id: placed_order.order.id,
+        amount: placed_order.total_price,
+        description: format!("Order {}", placed_order.order.id),
+    };
+
+    let payment = match payment_client.create_payment(&payment_request).await {
+        Ok(payment) => payment,
+        Err(e) => {
+            let _ = repository.run(move |conn| Order::set_status(conn, order_id, OrderStatus::Failed)).await;
+            return Err(e.into());
+        }
+    };
+
+    let order = repository
+        .run(move |conn| Order::set_payment(conn, order_id, &payment.id, payment.checkout_url.as_deref()))
+        .await?;
+    Ok(HttpResponse::Created().json(OrderWithProducts { order, ..placed_order }))
+}
+
+pub async fn get_orders(req: HttpRequest, auth: AuthUser, repository: web::Data<Repository>) -> Result<HttpResponse, AppError> {
+    let user_id = auth.user(&repository).await?.id;
+    let locale = preferred_locale(&req);
+    let orders = repository.run(move |conn| Order::find_all_for_user(conn, user_id, &locale)).await?;
+    Ok(HttpResponse::Ok().json(orders))
+}
+
+pub async fn get_order(req: HttpRequest, auth: AuthUser, repository: web::Data<Repository>, order_id: web::Path<Uuid>) -> Result<HttpResponse, AppError> {
+    let user_id = auth.user(&repository).await?.id;
+    let order_id = order_id.into_inner();
+    let locale = preferred_locale(&req);
+    let order = repository
+        .run(move |conn| Order::find_for_user(conn, user_id, order_id, &locale))
+        .await?
+        .ok_or_else(order_not_found)?;
+    Ok(HttpResponse::Ok().json(order))
+}
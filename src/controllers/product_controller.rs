@@ -1,11 +1,11 @@
-use crate::models::product::Product;
-use accept_language::intersection_with_quality;
-use actix_web::{web, HttpRequest, HttpResponse, Responder};
-use diesel::r2d2::{self, ConnectionManager};
-use diesel::PgConnection;
+use crate::errors::AppError;
+use crate::locale::preferred_locale;
+use crate::models::product::{Product, ProductForm};
+use crate::repository::Repository;
+use crate::validation::Validated;
+use actix_web::{web, HttpRequest, HttpResponse};
 use serde::Deserialize;
-use serde_json::json;
-type DbPool = r2d2::Pool<ConnectionManager<PgConnection>>;
+use uuid::Uuid;
 
 #[derive(Deserialize)]
 pub struct QueryParams {
@@ -14,39 +14,54 @@ pub struct QueryParams {
 
 pub async fn translated_products_handler(
     req: HttpRequest,
-    pool: web::Data<DbPool>,
+    repository: web::Data<Repository>,
     query_params: web::Query<QueryParams>,
-) -> impl Responder {
-    // Extract "Accept-Language" header
-    let header_value = req
-        .headers()
-        .get("Accept-Language")
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or("");
+) -> Result<HttpResponse, AppError> {
+    let selected_language = preferred_locale(&req);
 
-    // Define the supported languages
-    let supported_languages = ["fr", "en", "zh"]; // French, English, Chinese
+    // Extract search query if available
+    let search_query = query_params.into_inner().search;
 
-    // Find the intersection with quality factor to determine the best match
-    let common_languages = intersection_with_quality(header_value, &supported_languages);
+    let products = repository
+        .run(move |conn| Product::get_products_grouped_by_category(conn, &selected_language, search_query.as_deref()))
+        .await?;
+    Ok(HttpResponse::Ok().json(products))
+}
 
-    // Select the highest quality language from the intersection result
-    let selected_language = common_languages.first().map_or("en", |(lang, _)| lang);
+pub async fn get_product(repository: web::Data<Repository>, product_id: web::Path<Uuid>) -> Result<HttpResponse, AppError> {
+    let product_id = product_id.into_inner();
+    let product = repository.run(move |conn| Product::find_details(conn, product_id)).await?;
+    Ok(HttpResponse::Ok().json(product))
+}
 
-    // Extract search query if available
-    let search_query = query_params.search.as_deref();
-
-    let connection_result = pool.get();
-
-    let mut connection = match connection_result {
-        Ok(conn) => conn,
-        Err(_) => {
-            return HttpResponse::InternalServerError()
-                .json(json!({"error": "Error getting DB connection from pool"}))
-        }
-    };
-    match Product::get_products_grouped_by_category(&mut *connection, selected_language, search_query) {
-        Ok(products) => HttpResponse::Ok().json(products),
-        Err(_) => HttpResponse::InternalServerError().finish(),
-    }
+pub async fn create_product(repository: web::Data<Repository>, form: Validated<ProductForm>) -> Result<HttpResponse, AppError> {
+    let form = form.into_inner();
+    let product = repository.run(move |conn| Product::create(conn, &form)).await?;
+    Ok(HttpResponse::Created().json(product))
+}
+
+pub async fn update_product(repository: web::Data<Repository>, product_id: web::Path<Uuid>, form: Validated<ProductForm>) -> Result<HttpResponse, AppError> {
+    let product_id = product_id.into_inner();
+    let form = form.into_inner();
+    let product = repository.run(move |conn| Product::update(conn, product_id, &form)).await?;
+    Ok(HttpResponse::Ok().json(product))
+}
+
+pub async fn activate_product(repository: web::Data<Repository>, product_id: web::Path<Uuid>) -> Result<HttpResponse, AppError> {
+    set_product_active(&repository, product_id.into_inner(), true).await
+}
+
+pub async fn deactivate_product(repository: web::Data<Repository>, product_id: web::Path<Uuid>) -> Result<HttpResponse, AppError> {
+    set_product_active(&repository, product_id.into_inner(), false).await
+}
+
+async fn set_product_active(repository: &Repository, product_id: Uuid, active: bool) -> Result<HttpResponse, AppError> {
+    let product = repository.run(move |conn| Product::set_active(conn, product_id, active)).await?;
+    Ok(HttpResponse::Ok().json(product))
+}
+
+pub async fn delete_product(repository: web::Data<Repository>, product_id: web::Path<Uuid>) -> Result<HttpResponse, AppError> {
+    let product_id = product_id.into_inner();
+    repository.run(move |conn| Product::delete(conn, product_id)).await?;
+    Ok(HttpResponse::NoContent().finish())
 }
@@ -1,14 +1,22 @@
-use actix_web::{web, HttpResponse};
-use diesel::r2d2::{self, ConnectionManager};
-use diesel::{PgConnection, QueryDsl};
-use diesel::RunQueryDsl;
-use crate::schema::users::dsl::*;  // Import the DSL for the users table
-use diesel::prelude::*; use diesel::insert_into;
-use crate::models::user::{User, UserForm, NewUser, UserConnectionForm, Claims, get_secret_key};
+use actix_web::{web, HttpRequest, HttpResponse};
+use diesel::{OptionalExtension, PgConnection};
+use crate::config::Config;
+use crate::errors::AppError;
+use crate::mailer::{Email, Mailer};
+use crate::middlewares::auth_user::AuthUser;
+use crate::middlewares::token_validation::{bearer_token, decode_token};
+use crate::throttling::{client_ip, LoginThrottle};
+use crate::validation::Validated;
+use crate::models::password_reset_token::PasswordResetToken;
+use crate::models::refresh_token::{RefreshToken, Rotation};
+use crate::models::revoked_access_token::RevokedAccessToken;
+use crate::repository::{blocking, Repository};
+use crate::models::user::{User, UserForm, NewUser, UserConnectionForm, Claims, RoleForm, TokenType, VerifyEmailForm, ForgotPasswordForm, ResetPasswordForm, UserProfile, access_token_lifetime};
 use serde::Deserialize;
+use validator::Validate;
+use std::sync::LazyLock;
+use uuid::Uuid;
 
-use jsonwebtoken::{encode, EncodingKey, Header};
-use chrono::Utc;  // For managing expiration times
 
 use serde_json::json;
 use argon2::{
@@ -19,180 +27,395 @@ use argon2::{
     Argon2
 };
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Validate)]
 pub struct RefreshTokenRequest {
+    #[validate(length(min = 1, message = "Refresh token is required"))]
     refresh_token: String,
 }
 
-type DbPool = r2d2::Pool<ConnectionManager<PgConnection>>;
-
-pub async fn get_all_users(pool: web::Data<DbPool>) -> HttpResponse {
-    let connection_result = pool.get();
-
-    let mut connection = match connection_result {
-        Ok(conn) => conn,
-        Err(_) => return HttpResponse::InternalServerError().json(json!({"error": "Error getting DB connection from pool"})),
-    };
-
-    match User::find_all(&mut connection) {
-        Ok(all_users) => HttpResponse::Ok().json(all_users),
-        Err(_) => HttpResponse::InternalServerError().json(json!({"error": "Error getting users from the database"})),
-    }
+pub async fn get_all_users(repository: web::Data<Repository>) -> Result<HttpResponse, AppError> {
+    let all_users = repository.run(User::find_all).await?;
+    Ok(HttpResponse::Ok().json(all_users))
 }
 
-pub async fn sign_up(pool: web::Data<DbPool>, user: web::Json<UserForm>) -> HttpResponse {
-    // Get a database connection from the pool
-    let connection_result = pool.get();
+pub async fn set_user_role(repository: web::Data<Repository>, user_id: web::Path<Uuid>, form: Validated<RoleForm>) -> Result<HttpResponse, AppError> {
+    let user_id = user_id.into_inner();
+    let new_role = form.role;
 
-    let mut connection = match connection_result {
-        Ok(conn) => conn,
-        Err(_) => return HttpResponse::InternalServerError().json(json!({"error": "Error getting DB connection from pool"})),
-    };
+    let user = repository
+        .run(move |conn| User::set_role(conn, user_id, new_role).optional())
+        .await?
+        .ok_or_else(|| AppError::not_found("user_not_found", "User not found"))?;
+    Ok(HttpResponse::Ok().json(user))
+}
 
+/// Hashes a password with Argon2 and a new salt, returning both.
+fn hash_password(plain_password: &str) -> Result<(String, String), argon2::password_hash::Error> {
     // Generate a salt for hashing the password
     let generated_salt = SaltString::generate(&mut OsRng);
 
     // Use Argon2 to hash the password
-    let argon2 = Argon2::default();
-    let password_hash = match argon2.hash_password(user.password.clone().as_bytes(), &generated_salt) {
-        Ok(hash) => hash.to_string(),
-        Err(_) => return HttpResponse::InternalServerError().json(json!({"error": "Error hashing password"})),
-    };
+    let password_hash = Argon2::default().hash_password(plain_password.as_bytes(), &generated_salt)?;
+    Ok((password_hash.to_string(), generated_salt.as_str().to_string()))
+}
 
-    // Create a new NewUser instance for inserting into the database
-    let new_user = NewUser {
-        name: &user.name,
-        email: &user.email,
-        password: &password_hash,
-        salt: &generated_salt.to_string(),
+/// Hashes a password on the blocking thread pool, Argon2 being slow by design.
+async fn hash_password_blocking(plain_password: String) -> Result<(String, String), AppError> {
+    Ok(blocking(move || hash_password(&plain_password)).await??)
+}
 
-    };
+/// Hash checked when there is no user to compare the password with, so that
+/// failing to sign in always costs one Argon2 verification.
+static DUMMY_PASSWORD_HASH: LazyLock<String> = LazyLock::new(|| {
+    hash_password("dummy password")
+        .map(|(password_hash, _)| password_hash)
+        .expect("Error hashing the dummy password")
+});
+
+/// Computes the dummy hash ahead of the first sign-in, which would otherwise be slower.
+pub fn init_dummy_password_hash() {
+    LazyLock::force(&DUMMY_PASSWORD_HASH);
+}
 
-    // Insert the new user into the database
-    match insert_into(users).values(&new_user).execute(&mut connection) {
-        Ok(_) => HttpResponse::Ok().json(json!({"message": "User created successfully"})),
-        Err(_) => HttpResponse::InternalServerError().json(json!({"error": "Error inserting user into the database"})),
+/// Verifies a password against a stored hash with Argon2. Without a hash, or with an
+/// unreadable one, the dummy hash is verified instead and the password is refused.
+fn verify_password(stored_hash: Option<&str>, plain_password: &str) -> bool {
+    let parsed_hash = stored_hash.and_then(|stored_hash| match PasswordHash::new(stored_hash) {
+        Ok(parsed_hash) => Some(parsed_hash),
+        Err(e) => {
+            log::error!("Invalid password hash format: {}", e);
+            None
+        },
+    });
+
+    match parsed_hash {
+        Some(parsed_hash) => Argon2::default().verify_password(plain_password.as_bytes(), &parsed_hash).is_ok(),
+        None => {
+            if let Ok(dummy_hash) = PasswordHash::new(&DUMMY_PASSWORD_HASH) {
+                let _ = Argon2::default().verify_password(plain_password.as_bytes(), &dummy_hash);
+            }
+            false
+        },
     }
 }
 
-pub async fn sign_in(pool: web::Data<DbPool>, connection_form: web::Json<UserConnectionForm>) -> HttpResponse {
-    // Get a database connection from the pool
-    let connection_result = pool.get();
-
-    let mut connection = match connection_result {
-        Ok(conn) => conn,
-        Err(_) => return HttpResponse::InternalServerError().json(json!({"error": "Error getting DB connection from pool"})),
+/// Sends the link verifying the user's email address.
+async fn send_verification_email(mailer: &dyn Mailer, user: &User) -> Result<(), AppError> {
+    let token = user
+        .email_verification_token()
+        .map_err(|e| AppError::internal("token_error", e))?;
+    let link = match &Config::global().verify_email_url {
+        Some(url) => url.replace("{token}", &token),
+        None => token,
     };
 
-    // Find the user by email
-    let found_user_result = users
-        .filter(email.eq(&connection_form.email))
-        .first::<User>(&mut connection);
-
-    let found_user = match found_user_result {
-        Ok(user) => user,
-        Err(_) => return HttpResponse::Unauthorized().json(json!({"error": "Invalid email or password"})),
+    let verification_email = Email {
+        to: user.email.clone(),
+        subject: "Verify your email address".to_string(),
+        body: format!("Hello {},\n\nPlease verify your email address by following this link:\n{}", user.name, link),
     };
+    mailer.send(&verification_email).await?;
+    Ok(())
+}
 
-    // Parse the stored password hash
-    let parsed_hash = match PasswordHash::new(&found_user.password) {
-        Ok(hash) => hash,
-        Err(_) => return HttpResponse::InternalServerError().json(json!({"error": "Invalid password hash format"})),
-    };
+/// Revokes an access token until it expires.
+fn revoke_access_token(connection: &mut PgConnection, claims: &Claims) -> Result<(), diesel::result::Error> {
+    let expires_at = chrono::DateTime::from_timestamp(claims.exp as i64, 0)
+        .map_or_else(|| chrono::Utc::now().naive_utc(), |exp| exp.naive_utc());
+    RevokedAccessToken::revoke(connection, &[claims.jti], expires_at)
+}
+
+fn too_many_attempts(code: &'static str, wait: chrono::Duration) -> AppError {
+    let retry_after = (wait.num_milliseconds() as u64).div_ceil(1000);
+    AppError::TooManyRequests { code, retry_after }
+}
 
-    // Verify the password using Argon2
-    let password_verification = Argon2::default().verify_password(
-        connection_form.password.as_bytes(),
-        &parsed_hash,
-    );
+fn invalid_refresh_token() -> AppError {
+    AppError::unauthorized("invalid_refresh_token", "Invalid refresh token")
+}
 
-    if password_verification.is_err() {
-        return HttpResponse::Unauthorized().json(json!({"error": "Invalid email or password"}));
+pub async fn sign_up(repository: web::Data<Repository>, mailer: web::Data<dyn Mailer>, user: Validated<UserForm>) -> Result<HttpResponse, AppError> {
+    let user = user.into_inner();
+    let (password_hash, generated_salt) = hash_password_blocking(user.password).await?;
+
+    // Insert the new user into the database, emails are unique
+    let created_user = repository
+        .run(move |conn| {
+            let new_user = NewUser {
+                name: &user.name,
+                email: &user.email,
+                password: &password_hash,
+                salt: &generated_salt,
+            };
+            User::create(conn, &new_user)
+        })
+        .await?;
+
+    // The account exists even if the email can't be sent, a new one can be requested later
+    if let Err(e) = send_verification_email(mailer.get_ref(), &created_user).await {
+        log::warn!("Verification email not sent to user {}: {}", created_user.id, e);
     }
 
-    // Create JWT claims
-    let access_token_expiration = Utc::now()
-        .checked_add_signed(chrono::Duration::minutes(15))  // Access token valid for 15 minutes
-        .expect("valid timestamp")
-        .timestamp() as usize;
+    Ok(HttpResponse::Created().json(UserProfile::from(created_user)))
+}
 
-    let refresh_token_expiration = Utc::now()
-        .checked_add_signed(chrono::Duration::days(7))  // Refresh token valid for 7 days
-        .expect("valid timestamp")
-        .timestamp() as usize;
+pub async fn verify_email(repository: web::Data<Repository>, form: Validated<VerifyEmailForm>) -> Result<HttpResponse, AppError> {
+    let invalid_token = || AppError::bad_request("invalid_verification_token", "Invalid or expired verification token");
 
-    let access_claims = Claims {
-        sub: found_user.email.clone(),  // Use the user's email as the subject
-        exp: access_token_expiration,
-    };
+    let claims = Claims::decode(&form.token, TokenType::EmailVerification, &Config::global().jwt_secret).map_err(|_| invalid_token())?;
 
-    let refresh_claims = Claims {
-        sub: found_user.email.clone(),  // Use the user's email as the subject
-        exp: refresh_token_expiration,
-    };
+    // The token is only valid for the address it was sent to
+    let verified_user = repository
+        .run(move |conn| match User::find_by_id(conn, claims.uid).optional()? {
+            Some(found_user) if found_user.email == claims.sub => User::mark_email_verified(conn, claims.uid).map(Some),
+            _ => Ok(None),
+        })
+        .await?
+        .ok_or_else(invalid_token)?;
+    Ok(HttpResponse::Ok().json(verified_user))
+}
+
+pub async fn resend_verification_email(repository: web::Data<Repository>, mailer: web::Data<dyn Mailer>, auth: AuthUser) -> Result<HttpResponse, AppError> {
+    let found_user = auth.user(&repository).await?;
+
+    if found_user.is_email_verified() {
+        return Err(AppError::conflict("email_already_verified", "Email address already verified"));
+    }
+
+    send_verification_email(mailer.get_ref(), found_user).await?;
+    Ok(HttpResponse::Ok().json(json!({"message": "Verification email sent"})))
+}
+
+pub async fn sign_in(
+    req: HttpRequest,
+    repository: web::Data<Repository>,
+    login_throttle: web::Data<LoginThrottle>,
+    connection_form: Validated<UserConnectionForm>,
+) -> Result<HttpResponse, AppError> {
+    let connection_form = connection_form.into_inner();
+
+    let client_ip = client_ip(&req);
+    let client_ip = client_ip.as_deref();
+
+    // Throttled clients are turned away before the password is even checked, the others
+    // have their attempt counted as failed until the password matches
+    if let Some(wait) = login_throttle.check_and_record(&connection_form.email, client_ip) {
+        return Err(too_many_attempts("too_many_sign_in_attempts", wait));
+    }
 
-    // Encode the tokens
-    let secret_key = get_secret_key();  // Retrieve the secret key from the environment
+    // Unknown emails count as failures too, so throttling doesn't reveal which accounts exist
+    let invalid_credentials = || AppError::unauthorized("invalid_credentials", "Invalid email or password");
 
-    let access_token = match encode(
-        &Header::default(),
-        &access_claims,
-        &EncodingKey::from_secret(secret_key.as_ref()),
-    ) {
-        Ok(t) => t,
-        Err(_) => return HttpResponse::InternalServerError().json(json!({"error": "Access token creation error"})),
+    // Errors before the password is checked don't count as failed attempts
+    let not_checked = |e: AppError| {
+        login_throttle.forgive(&connection_form.email, client_ip);
+        e
     };
 
-    let refresh_token = match encode(
-        &Header::default(),
-        &refresh_claims,
-        &EncodingKey::from_secret(secret_key.as_ref()),  // Replace with your secret key
-    ) {
-        Ok(t) => t,
-        Err(_) => return HttpResponse::InternalServerError().json(json!({"error": "Refresh token creation error"})),
+    // Find the user by email
+    let lookup_email = connection_form.email.clone();
+    let found_user = repository
+        .run(move |conn| User::find_by_email(conn, &lookup_email).optional())
+        .await
+        .map_err(not_checked)?;
+
+    // A password is verified whether the email exists or not, and both failures get
+    // the same response, so neither the timing nor the body reveal registered emails
+    let stored_hash = found_user.as_ref().map(|user| user.password.clone());
+    let plain_password = connection_form.password.clone();
+    let password_matches = blocking(move || verify_password(stored_hash.as_deref(), &plain_password))
+        .await
+        .map_err(not_checked)?;
+    let found_user = match found_user {
+        Some(user) if password_matches => user,
+        _ => return Err(invalid_credentials()),
     };
+    login_throttle.record_success(&connection_form.email, client_ip);
+
+    // Create and encode the access token
+    let access_claims = Claims::new(&found_user, TokenType::Access, Uuid::new_v4(), access_token_lifetime());
+    let access_token = access_claims.encode(&Config::global().jwt_secret).map_err(|e| AppError::internal("token_error", e))?;
+
+    // The refresh token starts a new session, stored server-side so it can be revoked
+    let access_token_jti = access_claims.jti;
+    let refresh_token = repository
+        .run(move |conn| RefreshToken::issue(conn, &found_user, None, access_token_jti))
+        .await?;
 
     // Return the tokens in the response
-    HttpResponse::Ok().json(json!({
+    Ok(HttpResponse::Ok().json(json!({
         "message": "Sign in successful",
         "access_token": access_token,
         "refresh_token": refresh_token
-    }))
+    })))
 }
 
-pub async fn refresh_token(req: web::Json<RefreshTokenRequest>) -> HttpResponse {
-    let refresh_token = &req.refresh_token;
-    let secret_key = get_secret_key();
-
-    let token_data = match jsonwebtoken::decode::<Claims>(
-        &refresh_token,
-        &jsonwebtoken::DecodingKey::from_secret(secret_key.as_ref()),
-        &jsonwebtoken::Validation::default(),
-    ) {
-        Ok(c) => c,
-        Err(_) => return HttpResponse::Unauthorized().json(json!({"error": "Invalid refresh token"})),
+pub async fn refresh_token(repository: web::Data<Repository>, req: Validated<RefreshTokenRequest>) -> Result<HttpResponse, AppError> {
+    // Only refresh tokens are accepted here, access tokens are rejected
+    Claims::decode(&req.refresh_token, TokenType::Refresh, &Config::global().jwt_secret).map_err(|_| invalid_refresh_token())?;
+
+    // Every refresh token is single-use: it's exchanged for a new one of the same session
+    let presented_token = req.into_inner().refresh_token;
+    let access_token_jti = Uuid::new_v4();
+    let rotation = repository
+        .run(move |conn| RefreshToken::rotate(conn, &presented_token, access_token_jti))
+        .await?;
+    let (found_user, new_refresh_token) = match rotation {
+        Rotation::Rotated { user, token } => (user, token),
+        Rotation::Reused => return Err(AppError::unauthorized("refresh_token_reused", "Refresh token reused, session revoked")),
+        Rotation::Invalid => return Err(invalid_refresh_token()),
     };
 
-    let new_access_token_expiration = Utc::now()
-        .checked_add_signed(chrono::Duration::minutes(15))  // New access token valid for 15 minutes
-        .expect("valid timestamp")
-        .timestamp() as usize;
+    let new_access_claims = Claims::new(&found_user, TokenType::Access, access_token_jti, access_token_lifetime());
+    let new_access_token = new_access_claims.encode(&Config::global().jwt_secret).map_err(|e| AppError::internal("token_error", e))?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "access_token": new_access_token,
+        "refresh_token": new_refresh_token
+    })))
+}
+
+/// Signs out of the session of the presented refresh token. The access token
+/// used to call this endpoint, if any, is revoked as well.
+pub async fn sign_out(http_req: HttpRequest, repository: web::Data<Repository>, req: Validated<RefreshTokenRequest>) -> Result<HttpResponse, AppError> {
+    Claims::decode(&req.refresh_token, TokenType::Refresh, &Config::global().jwt_secret).map_err(|_| invalid_refresh_token())?;
+
+    // The route isn't authenticated, so the access token is decoded here: it's revoked
+    // along with the session when valid, and ignored otherwise
+    let access_claims = bearer_token(&http_req).and_then(|token| decode_token(token).ok());
+    let presented_token = req.into_inner().refresh_token;
+    let revoked = repository
+        .run(move |conn| {
+            if let Some(access_claims) = &access_claims {
+                revoke_access_token(conn, access_claims)?;
+            }
+            RefreshToken::revoke_session(conn, &presented_token)
+        })
+        .await?;
+
+    if !revoked {
+        return Err(invalid_refresh_token());
+    }
+    Ok(HttpResponse::Ok().json(json!({"message": "Sign out successful"})))
+}
+
+/// Signs the user out of every session, on every device.
+pub async fn sign_out_all(repository: web::Data<Repository>, auth: AuthUser) -> Result<HttpResponse, AppError> {
+    let access_claims = auth.claims().clone();
+    repository
+        .run(move |conn| {
+            revoke_access_token(conn, &access_claims)?;
+            RefreshToken::revoke_all_for_user(conn, access_claims.uid)
+        })
+        .await?;
+
+    Ok(HttpResponse::Ok().json(json!({"message": "Signed out of all sessions"})))
+}
 
-    let new_access_claims = Claims {
-        sub: token_data.claims.sub,  // Keep the same subject
-        exp: new_access_token_expiration,
+/// Sends a password reset link if the email belongs to a user. The response is the
+/// same either way, so it can't be used to find out which addresses have an account.
+/// Requests are throttled by email and IP, whether the account exists or not.
+pub async fn forgot_password(
+    req: HttpRequest,
+    repository: web::Data<Repository>,
+    mailer: web::Data<dyn Mailer>,
+    login_throttle: web::Data<LoginThrottle>,
+    form: Validated<ForgotPasswordForm>,
+) -> Result<HttpResponse, AppError> {
+    let sent_response = HttpResponse::Ok().json(json!({"message": "If an account exists for this email, a password reset link has been sent"}));
+
+    let lookup_email = form.into_inner().email;
+    if let Some(wait) = login_throttle.check_and_record_password_reset(&lookup_email, client_ip(&req).as_deref()) {
+        return Err(too_many_attempts("too_many_password_reset_requests", wait));
+    }
+
+    let issued = repository
+        .run(move |conn| match User::find_by_email(conn, &lookup_email).optional()? {
+            Some(found_user) => PasswordResetToken::issue(conn, found_user.id).map(|token| Some((found_user, token))),
+            None => Ok(None),
+        })
+        .await?;
+    let (found_user, token) = match issued {
+        Some(issued) => issued,
+        None => return Ok(sent_response),
     };
 
-    let new_access_token = match encode(
-        &Header::default(),
-        &new_access_claims,
-        &EncodingKey::from_secret(secret_key.as_ref()),
-    ) {
-        Ok(t) => t,
-        Err(_) => return HttpResponse::InternalServerError().json(json!({"error": "New access token creation error"})),
+    let link = match &Config::global().reset_password_url {
+        Some(url) => url.replace("{token}", &token),
+        None => token,
     };
 
-    HttpResponse::Ok().json(json!({
-        "access_token": new_access_token
-    }))
-}
\ No newline at end of file
+    let reset_email = Email {
+        to: found_user.email.clone(),
+        subject: "Reset your password".to_string(),
+        body: format!(
+            "Hello {},\n\nYou can choose a new password by following this link:\n{}\n\nIf you didn't ask for it, you can ignore this email.",
+            found_user.name, link
+        ),
+    };
+    // Failing here would tell that the address has an account, so the error is only logged
+    if let Err(e) = mailer.send(&reset_email).await {
+        log::warn!("Password reset email not sent to user {}: {}", found_user.id, e);
+    }
+
+    Ok(sent_response)
+}
+
+/// Sets a new password with a reset token. Every session of the user is revoked.
+pub async fn reset_password(repository: web::Data<Repository>, form: Validated<ResetPasswordForm>) -> Result<HttpResponse, AppError> {
+    let form = form.into_inner();
+    let invalid_token = || AppError::bad_request("invalid_reset_token", "Invalid or expired password reset token");
+
+    // The token is checked before the costly hashing, which unknown tokens must not trigger.
+    // It's checked again, locked, when the password is replaced
+    let token = form.token.clone();
+    if !repository.run(move |conn| PasswordResetToken::is_valid(conn, &token)).await? {
+        return Err(invalid_token());
+    }
+    let (password_hash, generated_salt) = hash_password_blocking(form.password).await?;
+
+    let reset = repository
+        .run(move |conn| PasswordResetToken::reset_password(conn, &form.token, &password_hash, &generated_salt))
+        .await?;
+    if !reset {
+        return Err(invalid_token());
+    }
+    Ok(HttpResponse::Ok().json(json!({"message": "Password reset successful"})))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    const RUNS: usize = 5;
+
+    fn median(mut durations: Vec<Duration>) -> Duration {
+        durations.sort();
+        durations[durations.len() / 2]
+    }
+
+    /// Signing in with an unknown email must cost about as much as a wrong password,
+    /// or response times would reveal which emails have an account.
+    #[test]
+    fn unknown_users_take_as_long_as_wrong_passwords() {
+        let (real_hash, _) = hash_password("correct horse battery").unwrap();
+        init_dummy_password_hash();
+
+        let mut with_hash = Vec::with_capacity(RUNS);
+        let mut without_hash = Vec::with_capacity(RUNS);
+        // Interleaved so that load changes on the machine affect both sides alike
+        for _ in 0..RUNS {
+            let start = Instant::now();
+            assert!(!verify_password(Some(&real_hash), "wrong password"));
+            with_hash.push(start.elapsed());
+
+            let start = Instant::now();
+            assert!(!verify_password(None, "wrong password"));
+            without_hash.push(start.elapsed());
+        }
+
+        let ratio = median(without_hash).as_secs_f64() / median(with_hash).as_secs_f64();
+        assert!((0.5..2.0).contains(&ratio), "unknown user took {:.2} times as long as a wrong password", ratio);
+    }
+}
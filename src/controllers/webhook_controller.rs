@@ -0,0 +1,52 @@
+use crate::errors::AppError;
+use crate::models::order::{Order, StatusChange};
+use crate::models::order_status::OrderStatus;
+use crate::payments::PaymentClient;
+use crate::repository::Repository;
+use actix_web::{web, HttpResponse};
+use crate::validation::validate_payment_id;
+use serde::Deserialize;
+use validator::Validate;
+
+#[derive(Deserialize, Validate)]
+pub struct MollieWebhookForm {
+    #[validate(custom(function = "validate_payment_id"))]
+    id: String,
+}
+
+/// Mollie only sends the payment id, the payment itself is fetched from the API so
+/// the webhook can't be spoofed. Mollie retries until it gets a 2xx, so unknown
+/// payments and refused transitions are acknowledged rather than reported as errors.
+pub async fn mollie_webhook(
+    repository: web::Data<Repository>,
+    payment_client: web::Data<dyn PaymentClient>,
+    form: web::Form<MollieWebhookForm>,
+) -> Result<HttpResponse, AppError> {
+    // The id is put in the path of a request made with our API key: anything else than a
+    // payment id is acknowledged without a lookup, as there is nothing Mollie could retry
+    if let Err(e) = form.validate() {
+        log::warn!("Mollie webhook called with an invalid payment id: {}", e);
+        return Ok(HttpResponse::Ok().finish());
+    }
+
+    let payment = match payment_client.get_payment(&form.id).await {
+        Ok(payment) => payment,
+        Err(e) if e.is_not_found() => return Ok(HttpResponse::Ok().finish()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let status = match OrderStatus::from_mollie(&payment.status) {
+        Some(status) => status,
+        None => return Ok(HttpResponse::Ok().finish()),
+    };
+
+    let payment_id = payment.id.clone();
+    match repository.run(move |conn| Order::apply_payment_status(conn, &payment.id, status)).await? {
+        Some(StatusChange::Updated(_)) | Some(StatusChange::Unchanged(_)) => {}
+        Some(StatusChange::Rejected(order)) => {
+            log::warn!("Mollie payment {} moved order {} from {} to {}, transition refused", payment_id, order.id, order.status, status);
+        }
+        None => log::warn!("Mollie payment {} doesn't match any order", payment_id),
+    }
+    Ok(HttpResponse::Ok().finish())
+}
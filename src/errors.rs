@@ -0,0 +1,284 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use actix_web::http::{header, StatusCode};
+use actix_web::{web, HttpResponse, ResponseError};
+use serde_json::{json, Value};
+use std::fmt;
+use validator::{ValidationErrors, ValidationErrorsKind};
+use crate::mailer::MailerError;
+use crate::models::order::OrderError;
+use crate::models::product::ProductError;
+use crate::models::product_category::CategoryError;
+use crate::models::refresh_token::TokenError;
+use crate::models::user::UserError;
+use crate::payments::PaymentError;
+
+/// Errors returned by the handlers. They are rendered as RFC 7807 `application/problem+json`
+/// bodies, whose `code` member is stable and meant to be matched by clients. The underlying
+/// cause of server errors is logged but never sent to the client.
+#[derive(Debug)]
+pub enum AppError {
+    /// No database connection could be taken from the pool.
+    Pool(r2d2::Error),
+    Database(diesel::result::Error),
+    Unauthorized { code: &'static str, detail: Cow<'static, str> },
+    Forbidden { code: &'static str, detail: Cow<'static, str> },
+    NotFound { code: &'static str, detail: Cow<'static, str> },
+    BadRequest { code: &'static str, detail: Cow<'static, str> },
+    Conflict { code: &'static str, detail: Cow<'static, str> },
+    /// The request is well-formed but breaks a business rule.
+    Unprocessable { code: &'static str, detail: Cow<'static, str> },
+    Validation(ValidationErrors),
+    /// Too many attempts, the client has to wait for `retry_after` seconds.
+    TooManyRequests { code: &'static str, retry_after: u64 },
+    /// A service we depend on, like the payment provider, failed.
+    Upstream { code: &'static str, cause: String },
+    Internal { code: &'static str, cause: String },
+}
+
+impl AppError {
+    pub fn unauthorized(code: &'static str, detail: impl Into<Cow<'static, str>>) -> Self {
+        AppError::Unauthorized { code, detail: detail.into() }
+    }
+
+    pub fn forbidden(code: &'static str, detail: impl Into<Cow<'static, str>>) -> Self {
+        AppError::Forbidden { code, detail: detail.into() }
+    }
+
+    pub fn not_found(code: &'static str, detail: impl Into<Cow<'static, str>>) -> Self {
+        AppError::NotFound { code, detail: detail.into() }
+    }
+
+    pub fn bad_request(code: &'static str, detail: impl Into<Cow<'static, str>>) -> Self {
+        AppError::BadRequest { code, detail: detail.into() }
+    }
+
+    pub fn conflict(code: &'static str, detail: impl Into<Cow<'static, str>>) -> Self {
+        AppError::Conflict { code, detail: detail.into() }
+    }
+
+    pub fn unprocessable(code: &'static str, detail: impl Into<Cow<'static, str>>) -> Self {
+        AppError::Unprocessable { code, detail: detail.into() }
+    }
+
+    pub fn internal(code: &'static str, cause: impl fmt::Display) -> Self {
+        AppError::Internal { code, cause: cause.to_string() }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::Pool(_) => "database_unavailable",
+            AppError::Database(_) => "database_error",
+            AppError::Validation(_) => "validation_failed",
+            AppError::Unauthorized { code, .. }
+            | AppError::Forbidden { code, .. }
+            | AppError::NotFound { code, .. }
+            | AppError::BadRequest { code, .. }
+            | AppError::Conflict { code, .. }
+            | AppError::Unprocessable { code, .. }
+            | AppError::TooManyRequests { code, .. }
+            | AppError::Upstream { code, .. }
+            | AppError::Internal { code, .. } => code,
+        }
+    }
+
+    /// Explanation sent to the client, which doesn't leak the details of server errors.
+    fn detail(&self) -> Cow<'static, str> {
+        match self {
+            AppError::Pool(_) => "The database is unavailable, please try again later".into(),
+            AppError::Database(_) | AppError::Internal { .. } => "An unexpected error occurred".into(),
+            AppError::Validation(_) => "The request contains invalid fields".into(),
+            AppError::Upstream { .. } => "An external service failed, please try again later".into(),
+            AppError::TooManyRequests { .. } => "Too many attempts, please retry after the delay given by Retry-After".into(),
+            AppError::Unauthorized { detail, .. }
+            | AppError::Forbidden { detail, .. }
+            | AppError::NotFound { detail, .. }
+            | AppError::BadRequest { detail, .. }
+            | AppError::Conflict { detail, .. }
+            | AppError::Unprocessable { detail, .. } => detail.clone(),
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::Pool(e) => write!(f, "Error getting DB connection from pool: {}", e),
+            AppError::Database(e) => write!(f, "Database error: {}", e),
+            AppError::Validation(e) => write!(f, "Validation failed: {}", e),
+            AppError::Upstream { code, cause } | AppError::Internal { code, cause } => write!(f, "{}: {}", code, cause),
+            _ => write!(f, "{}: {}", self.code(), self.detail()),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl ResponseError for AppError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::Pool(_) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::Database(_) | AppError::Internal { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::Unauthorized { .. } => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden { .. } => StatusCode::FORBIDDEN,
+            AppError::NotFound { .. } => StatusCode::NOT_FOUND,
+            AppError::BadRequest { .. } => StatusCode::BAD_REQUEST,
+            AppError::Conflict { .. } => StatusCode::CONFLICT,
+            AppError::Unprocessable { .. } | AppError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::TooManyRequests { .. } => StatusCode::TOO_MANY_REQUESTS,
+            AppError::Upstream { .. } => StatusCode::BAD_GATEWAY,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+        if status.is_server_error() {
+            log::error!("{}", self);
+        } else {
+            log::debug!("{}", self);
+        }
+
+        let mut problem = json!({
+            "type": "about:blank",
+            "title": status.canonical_reason().unwrap_or("Error"),
+            "status": status.as_u16(),
+            "detail": self.detail(),
+            "code": self.code(),
+        });
+        if let AppError::Validation(errors) = self {
+            problem["errors"] = json!(field_errors(errors));
+        }
+
+        let mut response = HttpResponse::build(status);
+        response.insert_header((header::CONTENT_TYPE, "application/problem+json"));
+        if let AppError::TooManyRequests { retry_after, .. } = self {
+            response.insert_header((header::RETRY_AFTER, retry_after.to_string()));
+        }
+        response.body(problem.to_string())
+    }
+}
+
+/// Renders the rejections of the built-in extractors like the errors of the handlers,
+/// instead of actix's plain text bodies.
+pub fn configure_extractors(cfg: &mut web::ServiceConfig) {
+    cfg.app_data(web::PathConfig::default().error_handler(|e, _| AppError::not_found("invalid_path", e.to_string()).into()))
+        .app_data(web::QueryConfig::default().error_handler(|e, _| AppError::bad_request("invalid_query", e.to_string()).into()))
+        .app_data(web::FormConfig::default().error_handler(|e, _| AppError::bad_request("invalid_body", e.to_string()).into()))
+        .app_data(web::JsonConfig::default().error_handler(|e, _| AppError::bad_request("invalid_body", e.to_string()).into()));
+}
+
+/// Errors by field, nested fields being named like `products[0].quantity`.
+fn field_errors(errors: &ValidationErrors) -> BTreeMap<String, Vec<Value>> {
+    let mut fields = BTreeMap::new();
+    collect_field_errors("", errors, &mut fields);
+    fields
+}
+
+fn collect_field_errors(prefix: &str, errors: &ValidationErrors, fields: &mut BTreeMap<String, Vec<Value>>) {
+    for (field, kind) in errors.errors() {
+        let path = if prefix.is_empty() { field.to_string() } else { format!("{}.{}", prefix, field) };
+        match kind {
+            ValidationErrorsKind::Field(field_errors) => {
+                fields.entry(path).or_default().extend(field_errors.iter().map(|error| json!({
+                    "code": error.code,
+                    "message": error.message.clone().unwrap_or_else(|| error.code.clone()),
+                })));
+            },
+            ValidationErrorsKind::Struct(nested) => collect_field_errors(&path, nested, fields),
+            ValidationErrorsKind::List(items) => {
+                for (index, nested) in items {
+                    collect_field_errors(&format!("{}[{}]", path, index), nested, fields);
+                }
+            },
+        }
+    }
+}
+
+impl From<r2d2::Error> for AppError {
+    fn from(e: r2d2::Error) -> Self {
+        AppError::Pool(e)
+    }
+}
+
+impl From<diesel::result::Error> for AppError {
+    fn from(e: diesel::result::Error) -> Self {
+        AppError::Database(e)
+    }
+}
+
+impl From<ValidationErrors> for AppError {
+    fn from(e: ValidationErrors) -> Self {
+        AppError::Validation(e)
+    }
+}
+
+impl From<argon2::password_hash::Error> for AppError {
+    fn from(e: argon2::password_hash::Error) -> Self {
+        AppError::internal("password_hash_error", e)
+    }
+}
+
+impl From<MailerError> for AppError {
+    fn from(e: MailerError) -> Self {
+        AppError::internal("mailer_error", e)
+    }
+}
+
+impl From<PaymentError> for AppError {
+    fn from(e: PaymentError) -> Self {
+        AppError::Upstream { code: "payment_provider_error", cause: e.to_string() }
+    }
+}
+
+impl From<TokenError> for AppError {
+    fn from(e: TokenError) -> Self {
+        match e {
+            TokenError::Jwt(e) => AppError::internal("token_error", e),
+            TokenError::Database(e) => AppError::Database(e),
+        }
+    }
+}
+
+impl From<UserError> for AppError {
+    fn from(e: UserError) -> Self {
+        match e {
+            UserError::EmailTaken => AppError::conflict("email_taken", e.to_string()),
+            UserError::Database(e) => AppError::Database(e),
+        }
+    }
+}
+
+impl From<OrderError> for AppError {
+    fn from(e: OrderError) -> Self {
+        match e {
+            OrderError::EmptyOrder => AppError::unprocessable("empty_order", e.to_string()),
+            OrderError::InvalidQuantity(_) => AppError::unprocessable("invalid_quantity", e.to_string()),
+            OrderError::UnavailableProduct(_) => AppError::unprocessable("unavailable_product", e.to_string()),
+            OrderError::Database(e) => AppError::Database(e),
+        }
+    }
+}
+
+impl From<ProductError> for AppError {
+    fn from(e: ProductError) -> Self {
+        match e {
+            ProductError::NotFound => AppError::not_found("product_not_found", e.to_string()),
+            ProductError::InvalidTranslations(_) => AppError::unprocessable("invalid_translations", e.to_string()),
+            ProductError::UnknownCategory(_) => AppError::unprocessable("unknown_category", e.to_string()),
+            ProductError::DuplicateSlug => AppError::conflict("duplicate_slug", e.to_string()),
+            ProductError::Ordered => AppError::conflict("product_ordered", e.to_string()),
+            ProductError::Database(e) => AppError::Database(e),
+        }
+    }
+}
+
+impl From<CategoryError> for AppError {
+    fn from(e: CategoryError) -> Self {
+        match e {
+            CategoryError::NotFound => AppError::not_found("category_not_found", e.to_string()),
+            CategoryError::InvalidTranslations(_) => AppError::unprocessable("invalid_translations", e.to_string()),
+            CategoryError::InvalidOrder(_) => AppError::unprocessable("invalid_category_order", e.to_string()),
+            CategoryError::Database(e) => AppError::Database(e),
+        }
+    }
+}
@@ -0,0 +1,32 @@
+use accept_language::intersection_with_quality;
+use actix_web::HttpRequest;
+use crate::config::Config;
+
+/// Locales the menu is translated into, French, English and Chinese by default.
+pub fn supported_locales() -> &'static [String] {
+    &Config::global().supported_locales
+}
+
+pub fn default_locale() -> &'static str {
+    &Config::global().default_locale
+}
+
+/// Picks the best supported locale from the request "Accept-Language" header.
+pub fn preferred_locale(req: &HttpRequest) -> String {
+    // Extract "Accept-Language" header
+    let header_value = req
+        .headers()
+        .get("Accept-Language")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("");
+
+    // Find the intersection with quality factor to determine the best match
+    let supported: Vec<&str> = supported_locales().iter().map(String::as_str).collect();
+    let common_languages = intersection_with_quality(header_value, &supported);
+
+    // Select the highest quality language from the intersection result
+    common_languages
+        .into_iter()
+        .next()
+        .map_or(default_locale().to_string(), |(lang, _)| lang)
+}
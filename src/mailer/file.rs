@@ -0,0 +1,57 @@
+use super::{Email, Mailer, MailerError};
+use crate::config::MailerTransport;
+use actix_web::web;
+use async_trait::async_trait;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Writes emails to a file, or to stdout when no file is given, instead of sending them.
+/// Meant for local development, where the links they contain can be followed by hand.
+pub struct FileMailer {
+    path: Option<PathBuf>,
+}
+
+impl FileMailer {
+    pub fn new(path: Option<PathBuf>) -> Self {
+        FileMailer { path }
+    }
+
+    pub fn from_config(transport: &MailerTransport) -> Self {
+        match transport {
+            MailerTransport::File(path) => FileMailer::new(Some(path.clone())),
+            MailerTransport::Stdout => FileMailer::new(None),
+        }
+    }
+}
+
+fn format_email(email: &Email) -> String {
+    format!("To: {}\nSubject: {}\n\n{}\n\n", email.to, email.subject, email.body)
+}
+
+fn write_email(path: Option<&Path>, message: &str) -> std::io::Result<()> {
+    match path {
+        Some(path) => {
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            file.write_all(message.as_bytes())
+        }
+        None => {
+            let mut stdout = std::io::stdout().lock();
+            stdout.write_all(message.as_bytes())?;
+            stdout.flush()
+        }
+    }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    /// The write runs on the blocking thread pool, like the database queries.
+    async fn send(&self, email: &Email) -> Result<(), MailerError> {
+        let message = format_email(email);
+        let path = self.path.clone();
+        web::block(move || write_email(path.as_deref(), &message))
+            .await
+            .map_err(std::io::Error::other)??;
+        Ok(())
+    }
+}
@@ -0,0 +1,41 @@
+pub mod file;
+
+use async_trait::async_trait;
+use std::fmt;
+
+pub use self::file::FileMailer;
+
+/// A plain text email.
+#[derive(Debug, Clone)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[derive(Debug)]
+pub enum MailerError {
+    Io(std::io::Error),
+}
+
+impl fmt::Display for MailerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MailerError::Io(e) => write!(f, "Error writing the email: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for MailerError {}
+
+impl From<std::io::Error> for MailerError {
+    fn from(e: std::io::Error) -> Self {
+        MailerError::Io(e)
+    }
+}
+
+/// Sends the emails of the application, kept behind a trait so the transport can be swapped.
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, email: &Email) -> Result<(), MailerError>;
+}
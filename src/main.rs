@@ -1,47 +1,123 @@
 extern crate diesel;
 
 pub mod schema;
+pub mod config;
+pub mod errors;
 pub mod middlewares;
 pub mod models;
 pub mod controllers;
 pub mod routes;
+pub mod locale;
+pub mod payments;
+pub mod mailer;
+pub mod validation;
+pub mod throttling;
+pub mod schema_check;
+pub mod migrations;
+pub mod repository;
 
-use actix_cors::Cors;
-use actix_web::{web, App, HttpServer, http::header};
+use actix_web::{web, App, HttpServer};
+use config::{Config, DatabaseConfig, MailerTransport};
 use diesel::r2d2::{self, ConnectionManager};
 use diesel::PgConnection;
 use dotenv::dotenv;
+use mailer::{FileMailer, Mailer};
+use migrations::MigrateCommand;
+use repository::{DbPool, Repository};
+use payments::{MollieClient, PaymentClient};
 use std::env;
+use std::sync::Arc;
+use throttling::{LoginThrottle, MemoryAttemptStore};
+use validation::password::PasswordPolicy;
 
-type DbPool = r2d2::Pool<ConnectionManager<PgConnection>>;
-
-async fn create_database_pool() -> DbPool {
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    let manager = ConnectionManager::<PgConnection>::new(database_url);
+async fn create_database_pool(config: &DatabaseConfig) -> DbPool {
+    let manager = ConnectionManager::<PgConnection>::new(&config.url);
     r2d2::Pool::builder()
+        .max_size(config.pool_size)
         .build(manager)
         .expect("Failed to create pool.")
 }
 
+const USAGE: &str = "Usage: tsb [--migrate-on-start]
+       tsb migrate <up|down|status>";
+
+enum Command {
+    Serve { migrate_on_start: bool },
+    Migrate(MigrateCommand),
+}
+
+fn parse_args(args: &[String]) -> Option<Command> {
+    match args {
+        [] => Some(Command::Serve { migrate_on_start: false }),
+        [flag] if flag == "--migrate-on-start" => Some(Command::Serve { migrate_on_start: true }),
+        [command, action] if command == "migrate" => MigrateCommand::parse(action).map(Command::Migrate),
+        _ => None,
+    }
+}
+
+fn exit_with_config_error(error: config::ConfigError) -> ! {
+    log::error!("{}", error);
+    std::process::exit(1);
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenv().ok();
-    let db_pool = create_database_pool().await;
-    let app_url: String = env::var("API_URL").expect("API_URL must be set");
+    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
 
-    HttpServer::new(move || {
-        let cors = Cors::default()
-            .allow_any_origin()
-            .allowed_methods(vec!["GET", "POST", "PUT", "DELETE", "OPTIONS", "HEAD"])
-            .allowed_headers(vec![header::AUTHORIZATION, header::ACCEPT, header::CONTENT_TYPE])
-            .max_age(3600);
+    let args: Vec<String> = env::args().skip(1).collect();
+    let migrate_on_start = match parse_args(&args) {
+        Some(Command::Serve { migrate_on_start }) => migrate_on_start,
+        Some(Command::Migrate(command)) => {
+            let database_config = DatabaseConfig::load().unwrap_or_else(|e| exit_with_config_error(e));
+            let db_pool = create_database_pool(&database_config).await;
+            let mut connection = db_pool.get().expect("Failed to get a database connection");
+            if let Err(e) = command.run(&mut connection) {
+                log::error!("Migration failed: {}", e);
+                std::process::exit(1);
+            }
+            return Ok(());
+        },
+        None => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        },
+    };
 
+    let config = Config::init(Config::load().unwrap_or_else(|e| exit_with_config_error(e)));
+    PasswordPolicy::init(PasswordPolicy::from_config(&config.password).unwrap_or_else(|e| exit_with_config_error(e)));
+    let db_pool = create_database_pool(&config.database).await;
+    {
+        let mut connection = db_pool.get().expect("Failed to get a database connection");
+        if migrate_on_start {
+            if let Err(e) = migrations::run_pending(&mut connection) {
+                log::error!("Migration failed: {}", e);
+                std::process::exit(1);
+            }
+        }
+        if let Err(drift) = schema_check::check(&mut connection) {
+            log::error!("{}", drift);
+            std::process::exit(1);
+        }
+    }
+    controllers::user_controller::init_dummy_password_hash();
+    let payment_client: Arc<dyn PaymentClient> = Arc::new(MollieClient::from_config(&config.mollie));
+    if let MailerTransport::Stdout = config.mailer {
+        log::warn!("MAILER_TRANSPORT is stdout: emails, with their verification and password reset tokens, are written to the logs. Don't use it in production");
+    }
+    let mailer: Arc<dyn Mailer> = Arc::new(FileMailer::from_config(&config.mailer));
+    let login_throttle = web::Data::new(LoginThrottle::from_config(&config.login, Arc::new(MemoryAttemptStore::new())));
+
+    HttpServer::new(move || {
         App::new()
-            .wrap(cors)
-            .app_data(web::Data::new(db_pool.clone()))
+            .app_data(web::Data::new(Repository::new(db_pool.clone())))
+            .app_data(web::Data::from(payment_client.clone()))
+            .app_data(web::Data::from(mailer.clone()))
+            .app_data(login_throttle.clone())
+            .configure(errors::configure_extractors)
             .configure(routes::configure)
     })
-        .bind(app_url)?
+        .bind(&config.api_url)?
         .run()
         .await
 }
@@ -0,0 +1,51 @@
+use std::cell::OnceCell;
+use std::future::{ready, Ready};
+use actix_web::{dev::Payload, Error, FromRequest, HttpRequest};
+use diesel::OptionalExtension;
+use crate::errors::AppError;
+use crate::middlewares::token_validation::claims_from_request;
+use crate::models::user::{Claims, User};
+use crate::repository::Repository;
+
+/// Extractor for the caller identified by the bearer token.
+///
+/// The claims come from the `Authentication` middleware, so the route must be wrapped
+/// with it; the `User` row is only loaded when a handler asks for it.
+pub struct AuthUser {
+    claims: Claims,
+    user: OnceCell<User>,
+}
+
+impl AuthUser {
+    pub fn claims(&self) -> &Claims {
+        &self.claims
+    }
+
+    /// Loads the user the token was issued to, once per request.
+    pub async fn user(&self, repository: &Repository) -> Result<&User, AppError> {
+        if let Some(user) = self.user.get() {
+            return Ok(user);
+        }
+        let user_id = self.claims.uid;
+        let user = repository
+            .run(move |conn| User::find_by_id(conn, user_id).optional())
+            .await?
+            .ok_or_else(|| AppError::unauthorized("unknown_user", "Unknown user"))?;
+        Ok(self.user.get_or_init(|| user))
+    }
+}
+
+impl FromRequest for AuthUser {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(match claims_from_request(req) {
+            Some(claims) => Ok(AuthUser {
+                claims,
+                user: OnceCell::new(),
+            }),
+            None => Err(AppError::unauthorized("invalid_token", "Invalid token").into()),
+        })
+    }
+}
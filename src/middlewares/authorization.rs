@@ -0,0 +1,66 @@
+use std::{future::{ready, Future, Ready}, pin::Pin};
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    Error,
+};
+use crate::errors::AppError;
+use crate::middlewares::token_validation::claims_from_request;
+use crate::models::role::Role;
+
+/// Rejects requests whose token role is below the required one.
+/// Wrap it inside `token_validation::Authentication` so missing tokens get a 401.
+pub struct RequireRole(pub Role);
+
+impl<S, B> Transform<S, ServiceRequest> for RequireRole
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RequireRoleMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequireRoleMiddleware { service, role: self.0 }))
+    }
+}
+
+pub struct RequireRoleMiddleware<S> {
+    service: S,
+    role: Role,
+}
+
+type LocalBoxFuture<T> = Pin<Box<dyn Future<Output = T> + 'static>>;
+
+impl<S, B> Service<ServiceRequest> for RequireRoleMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let error = match claims_from_request(req.request()) {
+            Some(claims) if claims.role >= self.role => {
+                let fut = self.service.call(req);
+                return Box::pin(async move {
+                    let res = fut.await?;
+                    Ok(res.map_into_left_body())
+                });
+            },
+            Some(_) => AppError::forbidden("insufficient_permissions", "Insufficient permissions"),
+            None => AppError::unauthorized("invalid_token", "Invalid token"),
+        };
+        // Answered rather than returned as an error, so the CORS headers are still added
+        Box::pin(async move { Ok(req.error_response(error).map_into_right_body()) })
+    }
+}
@@ -0,0 +1,49 @@
+use actix_cors::Cors;
+use actix_web::http::{header, Method};
+use crate::config::{Config, CorsConfig};
+
+// Each policy wraps its routes outside of any authentication middleware,
+// so preflight requests, which carry no credentials, are answered first.
+
+/// Policy of the public menu: read-only, without credentials.
+pub fn public() -> Cors {
+    let config = &Config::global().cors;
+    with_origins(Cors::default(), &config.public_allowed_origins)
+        .allowed_methods(vec![Method::GET, Method::HEAD, Method::OPTIONS])
+        .allowed_headers(vec![header::ACCEPT, header::ACCEPT_LANGUAGE])
+        .max_age(config.max_age)
+}
+
+/// Policy of the API used by customers.
+pub fn api() -> Cors {
+    let config = &Config::global().cors;
+    configured(config, &config.allowed_origins)
+}
+
+/// Policy of the admin scopes, limited to the admin origins.
+pub fn admin() -> Cors {
+    let config = &Config::global().cors;
+    configured(config, &config.admin_allowed_origins)
+}
+
+fn configured(config: &CorsConfig, origins: &[String]) -> Cors {
+    let cors = with_origins(Cors::default(), origins)
+        .allowed_methods(config.allowed_methods.clone())
+        .allowed_headers(config.allowed_headers.clone())
+        .max_age(config.max_age);
+    if config.allow_credentials {
+        cors.supports_credentials()
+    } else {
+        cors
+    }
+}
+
+/// Without any origin, cross-origin requests are refused. The configuration
+/// only allows `*` on its own, so it never mixes with explicit origins.
+fn with_origins(cors: Cors, origins: &[String]) -> Cors {
+    if origins.iter().any(|origin| origin == "*") {
+        cors.allow_any_origin()
+    } else {
+        origins.iter().fold(cors, |cors, origin| cors.allowed_origin(origin))
+    }
+}
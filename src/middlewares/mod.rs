@@ -1 +1,4 @@
+pub mod auth_user;
+pub mod authorization;
+pub mod cors;
 pub mod token_validation;
\ No newline at end of file
@@ -1,93 +1,110 @@
-use std::{future::{ready, Future, Ready}, pin::Pin};
+use std::{future::{ready, Future, Ready}, pin::Pin, rc::Rc};
 use actix_web::{
-    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform}, 
-    Error, HttpResponse,
+    body::EitherBody,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    web, Error, HttpMessage, HttpRequest,
 };
-use jsonwebtoken::{decode, DecodingKey, Validation, Algorithm};
-use crate::models::user::{Claims, get_secret_key};
+use crate::config::Config;
+use crate::errors::AppError;
+use crate::models::revoked_access_token::RevokedAccessToken;
+use crate::models::user::{Claims, TokenType};
+use crate::repository::Repository;
+
+/// Decodes and validates a bearer token, which must be an access token.
+pub fn decode_token(token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
+    Claims::decode(token, TokenType::Access, &Config::global().jwt_secret)
+}
+
+/// Returns the claims the `Authentication` middleware placed on the request. Routes that
+/// aren't wrapped with it get `None`, since the token was never checked against the
+/// revoked ones.
+pub fn claims_from_request(req: &HttpRequest) -> Option<Claims> {
+    req.extensions().get::<Claims>().cloned()
+}
+
+/// Returns the raw bearer token of the request, if any, without validating it.
+pub fn bearer_token(req: &HttpRequest) -> Option<&str> {
+    let auth_value = req.headers().get("Authorization")?.to_str().ok()?;
+    Some(auth_value.trim_start_matches("Bearer ").trim())
+}
+
+/// Decodes the bearer token of the request, rejecting tokens of signed out sessions until they expire.
+async fn authenticate(req: &ServiceRequest) -> Result<Claims, AppError> {
+    let auth_data = req
+        .headers()
+        .get("Authorization")
+        .ok_or_else(|| AppError::unauthorized("missing_token", "Authorization header not found"))?;
+    let auth_value = auth_data
+        .to_str()
+        .map_err(|e| AppError::unauthorized("invalid_header", format!("Invalid header format: {}", e)))?;
+
+    // Extract the token from the "Bearer <token>" format
+    let token = auth_value.trim_start_matches("Bearer ").trim();
+
+    // Perform JWT validation
+    let claims = decode_token(token).map_err(|_| AppError::unauthorized("invalid_token", "Invalid token"))?;
+
+    let repository = req
+        .app_data::<web::Data<Repository>>()
+        .ok_or_else(|| AppError::internal("missing_repository", "Repository not configured"))?;
+    let jti = claims.jti;
+    if repository.run(move |conn| RevokedAccessToken::is_revoked(conn, jti)).await? {
+        return Err(AppError::unauthorized("token_revoked", "Token revoked"));
+    }
+    Ok(claims)
+}
 
 pub struct Authentication;
 
 impl<S, B> Transform<S, ServiceRequest> for Authentication 
 where 
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     S::Future: 'static,
     B: 'static,
 {
-    type Response = ServiceResponse<B>;
+    type Response = ServiceResponse<EitherBody<B>>;
     type Error = Error;
     type Transform = AuthenticationMiddleware<S>;
     type InitError = ();
     type Future = Ready<Result<Self::Transform, Self::InitError>>;
     
     fn new_transform(&self, service: S) -> Self::Future {
-        ready(Ok(AuthenticationMiddleware { service }))
+        ready(Ok(AuthenticationMiddleware { service: Rc::new(service) }))
     }
 }
 
 pub struct AuthenticationMiddleware<S> {
-    service: S,
+    service: Rc<S>,
 }
 
 type LocalBoxFuture<T> = Pin<Box<dyn Future<Output = T> + 'static>>;
 
 impl<S, B> Service<ServiceRequest> for AuthenticationMiddleware<S> 
 where
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     S::Future: 'static,
     B: 'static,
 {
-    type Response = ServiceResponse<B>;
+    type Response = ServiceResponse<EitherBody<B>>;
     type Error = Error;
     type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
 
     forward_ready!(service);
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
-        if let Some(auth_data) = req.headers().get("Authorization") {
-            match auth_data.to_str() {
-                Ok(auth_value) => {
-                    // Extract the token from the "Bearer <token>" format
-                    let token = auth_value.trim_start_matches("Bearer ").trim();
+        let service = Rc::clone(&self.service);
+        Box::pin(async move {
+            let claims = match authenticate(&req).await {
+                Ok(claims) => claims,
+                // Answered here rather than returned as an error, so the outer CORS
+                // middleware still adds its headers and browsers can read the 401
+                Err(e) => return Ok(req.error_response(e).map_into_right_body()),
+            };
 
-                    // Perform JWT validation
-                    let validation = Validation::new(Algorithm::HS256);
-                    let secret = get_secret_key();
-                    match decode::<Claims>(&token, &DecodingKey::from_secret(secret.as_ref()), &validation) {
-                        Ok(_) => {
-                            // If the authorization is valid, continue to the next service:
-                            let fut = self.service.call(req);
-                            return Box::pin(async move {
-                                let res = fut.await?;
-                                Ok(res)
-                            });
-                        },
-                        Err(_) => {
-                            // Handle invalid token
-                            return Box::pin(async move {
-                                let response = HttpResponse::Unauthorized()
-                                    .json(serde_json::json!({"error": "Invalid token"}));
-                                Err(actix_web::error::InternalError::from_response("Invalid token", response).into())
-                            });
-                        }
-                    }
-                },
-                Err(e) => {
-                    // Handle header value parsing error
-                    return Box::pin(async move {
-                        let response = HttpResponse::Unauthorized()
-                            .json(serde_json::json!({"error": format!("Invalid header format: {}", e)}));
-                        Err(actix_web::error::InternalError::from_response("Invalid header format", response).into())
-                    });
-                },
-            }
-        } else {
-            // No Authorization header found
-            return Box::pin(async {
-                let response = HttpResponse::Unauthorized()
-                    .json(serde_json::json!({"error": "Authorization header not found"}));
-                Err(actix_web::error::InternalError::from_response("Authorization header not found", response).into())
-            });
-        }
+            // Keep the claims for the extractors, then continue to the next service:
+            req.extensions_mut().insert(claims);
+            let res = service.call(req).await?;
+            Ok(res.map_into_left_body())
+        })
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,67 @@
+use diesel::migration::MigrationSource;
+use diesel::pg::{Pg, PgConnection};
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+use std::collections::HashSet;
+
+/// The `migrations` directory, embedded at compile time so the binary can migrate
+/// its database without the diesel CLI.
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
+
+type MigrationResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync + 'static>>;
+
+pub enum MigrateCommand {
+    /// Applies every pending migration.
+    Up,
+    /// Reverts the last applied migration.
+    Down,
+    /// Lists migrations and whether they are applied.
+    Status,
+}
+
+impl MigrateCommand {
+    pub fn parse(arg: &str) -> Option<MigrateCommand> {
+        match arg {
+            "up" => Some(MigrateCommand::Up),
+            "down" => Some(MigrateCommand::Down),
+            "status" => Some(MigrateCommand::Status),
+            _ => None,
+        }
+    }
+
+    pub fn run(&self, conn: &mut PgConnection) -> MigrationResult<()> {
+        match self {
+            MigrateCommand::Up => {
+                let applied = run_pending(conn)?;
+                if applied == 0 {
+                    println!("No pending migrations");
+                }
+            },
+            MigrateCommand::Down => {
+                let version = conn.revert_last_migration(MIGRATIONS)?;
+                println!("Reverted {}", version);
+            },
+            MigrateCommand::Status => {
+                let applied: HashSet<String> = conn
+                    .applied_migrations()?
+                    .into_iter()
+                    .map(|version| version.to_string())
+                    .collect();
+                for migration in MigrationSource::<Pg>::migrations(&MIGRATIONS)? {
+                    let name = migration.name();
+                    let mark = if applied.contains(&name.version().to_string()) { "X" } else { " " };
+                    println!("[{}] {}", mark, name);
+                }
+            },
+        }
+        Ok(())
+    }
+}
+
+/// Applies every pending migration and returns how many were applied.
+pub fn run_pending(conn: &mut PgConnection) -> MigrationResult<usize> {
+    let versions = conn.run_pending_migrations(MIGRATIONS)?;
+    for version in &versions {
+        log::info!("Applied migration {}", version);
+    }
+    Ok(versions.len())
+}
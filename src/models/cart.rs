@@ -0,0 +1,239 @@
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::upsert::excluded;
+use uuid::Uuid;
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+use crate::models::order::{Order, OrderError, OrderForm, OrderProductForm, MAX_QUANTITY};
+use crate::models::payment_mode::PaymentMode;
+use crate::schema::{cart_product, product_translations, products};
+
+#[derive(Insertable)]
+#[diesel(table_name = cart_product)]
+pub struct NewCartProduct {
+    pub user_id: Uuid,
+    pub product_id: Uuid,
+    pub quantity: i32,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct CartProductForm {
+    pub product_id: Uuid,
+    #[validate(range(min = 1, max = MAX_QUANTITY, message = "Quantity must be between 1 and 1000"))]
+    pub quantity: i32,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct CartQuantityForm {
+    #[validate(range(min = 1, max = MAX_QUANTITY, message = "Quantity must be between 1 and 1000"))]
+    pub quantity: i32,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct CheckoutForm {
+    pub payment_mode: PaymentMode,
+}
+
+/// An order placed from the cart, with the lines it took out of the cart.
+pub struct Checkout {
+    pub order: Order,
+    pub lines: Vec<OrderProductForm>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CartLine {
+    pub product_id: Uuid,
+    pub name: Option<String>,
+    pub code: Option<String>,
+    pub quantity: i32,
+    pub unit_price: Option<f64>,
+    pub total_price: Option<f64>,
+    /// False when the product was deactivated or unpriced since it was added.
+    pub is_available: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Cart {
+    pub products: Vec<CartLine>,
+    pub total_price: f64,
+}
+
+impl Cart {
+    /// Returns the cart of a user with prices from the current menu, translated in `locale`.
+    pub fn find_for_user(conn: &mut PgConnection, user_id: Uuid, locale: &str) -> Result<Cart, diesel::result::Error> {
+        let raw_lines = cart_product::table
+            .inner_join(products::table)
+            .left_join(product_translations::table.on(
+                product_translations::product_id.eq(cart_product::product_id)
+                    .and(product_translations::locale.eq(locale))
+            ))
+            .filter(cart_product::user_id.eq(user_id))
+            .order(cart_product::created_at.asc())
+            .select((
+                cart_product::product_id,
+                product_translations::name.nullable(),
+                products::code,
+                cart_product::quantity,
+                products::price,
+                products::is_active,
+            ))
+            .load::<(Uuid, Option<String>, Option<String>, i32, Option<f64>, bool)>(conn)?;
+
+        let products: Vec<CartLine> = raw_lines
+            .into_iter()
+            .map(|(product_id, name, code, quantity, price, is_active)| CartLine {
+                product_id,
+                name,
+                code,
+                quantity,
+                unit_price: price,
+                total_price: price.map(|p| p * quantity as f64),
+                is_available: is_active && price.is_some(),
+            })
+            .collect();
+
+        let total_price = products
+            .iter()
+            .filter(|line| line.is_available)
+            .filter_map(|line| line.total_price)
+            .fold(0.0, |total, price| total + price);
+
+        Ok(Cart { products, total_price })
+    }
+
+    /// Adds a product to the cart, or increases its quantity if it's already there.
+    /// The total quantity can't go over `MAX_QUANTITY`.
+    pub fn add_product(conn: &mut PgConnection, user_id: Uuid, form: &CartProductForm) -> Result<(), OrderError> {
+        if form.quantity <= 0 || form.quantity > MAX_QUANTITY {
+            return Err(OrderError::InvalidQuantity(form.product_id));
+        }
+        Self::ensure_available(conn, form.product_id)?;
+
+        conn.transaction(|conn| {
+            let current_quantity = cart_product::table
+                .find((user_id, form.product_id))
+                .select(cart_product::quantity)
+                .for_update()
+                .first::<i32>(conn)
+                .optional()?
+                .unwrap_or(0);
+            let quantity = current_quantity
+                .checked_add(form.quantity)
+                .filter(|quantity| *quantity <= MAX_QUANTITY)
+                .ok_or(OrderError::InvalidQuantity(form.product_id))?;
+
+            diesel::insert_into(cart_product::table)
+                .values(&NewCartProduct {
+                    user_id,
+                    product_id: form.product_id,
+                    quantity,
+                })
+                .on_conflict((cart_product::user_id, cart_product::product_id))
+                .do_update()
+                .set(cart_product::quantity.eq(excluded(cart_product::quantity)))
+                .execute(conn)?;
+
+            Ok(())
+        })
+    }
+
+    /// Sets the quantity of a product already in the cart. Returns false if it isn't in the cart.
+    pub fn update_product(conn: &mut PgConnection, user_id: Uuid, product_id: Uuid, quantity: i32) -> Result<bool, OrderError> {
+        if quantity <= 0 || quantity > MAX_QUANTITY {
+            return Err(OrderError::InvalidQuantity(product_id));
+        }
+
+        let updated = diesel::update(cart_product::table.find((user_id, product_id)))
+            .set(cart_product::quantity.eq(quantity))
+            .execute(conn)?;
+
+        Ok(updated > 0)
+    }
+
+    /// Removes a product from the cart. Returns false if it wasn't in the cart.
+    pub fn remove_product(conn: &mut PgConnection, user_id: Uuid, product_id: Uuid) -> Result<bool, diesel::result::Error> {
+        let deleted = diesel::delete(cart_product::table.find((user_id, product_id))).execute(conn)?;
+        Ok(deleted > 0)
+    }
+
+    pub fn clear(conn: &mut PgConnection, user_id: Uuid) -> Result<(), diesel::result::Error> {
+        diesel::delete(cart_product::table.filter(cart_product::user_id.eq(user_id))).execute(conn)?;
+        Ok(())
+    }
+
+    /// Turns the cart into an order and removes the ordered lines, in a single transaction.
+    /// The lines are locked until then, so a second checkout of the same cart waits and
+    /// finds it empty instead of ordering it again.
+    pub fn checkout(conn: &mut PgConnection, user_id: Uuid, payment_mode: PaymentMode) -> Result<Checkout, OrderError> {
+        conn.transaction(|conn| {
+            let lines = cart_product::table
+                .filter(cart_product::user_id.eq(user_id))
+                .order(cart_product::created_at.asc())
+                .select((cart_product::product_id, cart_product::quantity))
+                .for_update()
+                .load::<(Uuid, i32)>(conn)?;
+
+            let form = OrderForm {
+                payment_mode,
+                products: lines
+                    .into_iter()
+                    .map(|(product_id, quantity)| OrderProductForm { product_id, quantity })
+                    .collect(),
+            };
+            let order = Order::place(conn, user_id, &form)?;
+
+            let product_ids: Vec<Uuid> = form.products.iter().map(|line| line.product_id).collect();
+            diesel::delete(
+                cart_product::table
+                    .filter(cart_product::user_id.eq(user_id))
+                    .filter(cart_product::product_id.eq_any(&product_ids)),
+            )
+            .execute(conn)?;
+
+            Ok(Checkout { order, lines: form.products })
+        })
+    }
+
+    /// Puts checked out lines back into the cart, e.g. when the order's payment couldn't be
+    /// created. Lines added in the meantime are kept, quantities being merged up to the maximum.
+    pub fn restore(conn: &mut PgConnection, user_id: Uuid, lines: &[OrderProductForm]) -> Result<(), diesel::result::Error> {
+        conn.transaction(|conn| {
+            for line in lines {
+                let current_quantity = cart_product::table
+                    .find((user_id, line.product_id))
+                    .select(cart_product::quantity)
+                    .for_update()
+                    .first::<i32>(conn)
+                    .optional()?
+                    .unwrap_or(0);
+                let quantity = current_quantity.saturating_add(line.quantity).min(MAX_QUANTITY);
+
+                diesel::insert_into(cart_product::table)
+                    .values(&NewCartProduct {
+                        user_id,
+                        product_id: line.product_id,
+                        quantity,
+                    })
+                    .on_conflict((cart_product::user_id, cart_product::product_id))
+                    .do_update()
+                    .set(cart_product::quantity.eq(excluded(cart_product::quantity)))
+                    .execute(conn)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn ensure_available(conn: &mut PgConnection, product_id: Uuid) -> Result<(), OrderError> {
+        let available = products::table
+            .filter(products::id.eq(product_id))
+            .filter(products::is_active.eq(true))
+            .filter(products::price.is_not_null())
+            .count()
+            .get_result::<i64>(conn)?;
+
+        if available == 0 {
+            return Err(OrderError::UnavailableProduct(product_id));
+        }
+        Ok(())
+    }
+}
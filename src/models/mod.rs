@@ -1,3 +1,12 @@
-//pub mod order;
+pub mod cart;
+pub mod order;
+pub mod order_status;
+pub mod password_reset_token;
+pub mod payment_mode;
 pub mod product;
+pub mod product_category;
+pub mod refresh_token;
+pub mod revoked_access_token;
+pub mod role;
+mod text_enum;
 pub mod user;
\ No newline at end of file
@@ -0,0 +1,305 @@
+use chrono::NaiveDateTime;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use uuid::Uuid;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use validator::Validate;
+use crate::models::order_status::OrderStatus;
+use crate::models::payment_mode::PaymentMode;
+use crate::schema::{order_product, orders, product_translations, products};
+
+#[derive(Serialize, Deserialize, Queryable, Selectable, Identifiable, Debug, Clone)]
+#[diesel(table_name = orders)]
+pub struct Order {
+    pub id: Uuid,
+    pub created_at: Option<NaiveDateTime>,
+    pub updated_at: Option<NaiveDateTime>,
+    pub user_id: Uuid,
+    pub payment_mode: PaymentMode,
+    pub mollie_payment_id: Option<String>,
+    pub mollie_payment_url: Option<String>,
+    pub status: OrderStatus,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = orders)]
+pub struct NewOrder {
+    pub user_id: Uuid,
+    pub payment_mode: PaymentMode,
+}
+
+#[derive(Serialize, Deserialize, Queryable, Selectable, Insertable, Debug, Clone)]
+#[diesel(table_name = order_product)]
+pub struct OrderProduct {
+    pub order_id: Uuid,
+    pub product_id: Uuid,
+    pub quantity: i32,
+    pub unit_price: f64,
+}
+
+/// Largest quantity of a product in a single order or cart.
+pub const MAX_QUANTITY: i32 = 1000;
+
+#[derive(Serialize, Deserialize, Validate)]
+pub struct OrderProductForm {
+    pub product_id: Uuid,
+    #[validate(range(min = 1, max = MAX_QUANTITY, message = "Quantity must be between 1 and 1000"))]
+    pub quantity: i32,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct OrderForm {
+    pub payment_mode: PaymentMode,
+    #[validate(length(min = 1, message = "An order must contain at least one product"), nested)]
+    pub products: Vec<OrderProductForm>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct OrderLine {
+    pub product_id: Uuid,
+    pub name: Option<String>,
+    pub code: Option<String>,
+    pub quantity: i32,
+    pub unit_price: f64,
+    pub total_price: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct OrderWithProducts {
+    #[serde(flatten)]
+    pub order: Order,
+    pub products: Vec<OrderLine>,
+    pub total_price: f64,
+}
+
+#[derive(Debug)]
+pub enum OrderError {
+    EmptyOrder,
+    InvalidQuantity(Uuid),
+    UnavailableProduct(Uuid),
+    Database(diesel::result::Error),
+}
+
+impl fmt::Display for OrderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrderError::EmptyOrder => write!(f, "An order must contain at least one product"),
+            OrderError::InvalidQuantity(id) => write!(f, "Invalid quantity for product {}", id),
+            OrderError::UnavailableProduct(id) => write!(f, "Product {} is not available", id),
+            OrderError::Database(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for OrderError {}
+
+impl From<diesel::result::Error> for OrderError {
+    fn from(e: diesel::result::Error) -> Self {
+        OrderError::Database(e)
+    }
+}
+
+/// Outcome of applying a payment status to an order.
+pub enum StatusChange {
+    Updated(Order),
+    /// The order already has this status, e.g. when a webhook is retried.
+    Unchanged(Order),
+    Rejected(Order),
+}
+
+impl Order {
+    /// Places an order for the given user. Every product must be active and priced;
+    /// prices are copied onto the order lines so later menu changes don't alter past orders.
+    pub fn place(conn: &mut PgConnection, user_id: Uuid, form: &OrderForm) -> Result<Order, OrderError> {
+        if form.products.is_empty() {
+            return Err(OrderError::EmptyOrder);
+        }
+
+        // Merge duplicated products into a single line, keeping the requested order
+        let mut quantities: Vec<(Uuid, i32)> = Vec::new();
+        for line in &form.products {
+            if line.quantity <= 0 || line.quantity > MAX_QUANTITY {
+                return Err(OrderError::InvalidQuantity(line.product_id));
+            }
+            match quantities.iter_mut().find(|(id, _)| *id == line.product_id) {
+                Some((_, quantity)) => {
+                    *quantity = quantity
+                        .checked_add(line.quantity)
+                        .filter(|merged| *merged <= MAX_QUANTITY)
+                        .ok_or(OrderError::InvalidQuantity(line.product_id))?;
+                },
+                None => quantities.push((line.product_id, line.quantity)),
+            }
+        }
+
+        conn.transaction(|conn| {
+            let product_ids: Vec<Uuid> = quantities.iter().map(|(id, _)| *id).collect();
+            let prices: HashMap<Uuid, f64> = products::table
+                .filter(products::id.eq_any(&product_ids))
+                .filter(products::is_active.eq(true))
+                .select((products::id, products::price))
+                .load::<(Uuid, Option<f64>)>(conn)?
+                .into_iter()
+                .filter_map(|(id, price)| price.map(|p| (id, p)))
+                .collect();
+
+            let mut lines = Vec::with_capacity(quantities.len());
+            for (product_id, quantity) in &quantities {
+                let unit_price = *prices
+                    .get(product_id)
+                    .ok_or(OrderError::UnavailableProduct(*product_id))?;
+                lines.push((*product_id, *quantity, unit_price));
+            }
+
+            let order: Order = diesel::insert_into(orders::table)
+                .values(&NewOrder {
+                    user_id,
+                    payment_mode: form.payment_mode,
+                })
+                .get_result(conn)?;
+
+            let order_lines: Vec<OrderProduct> = lines
+                .into_iter()
+                .map(|(product_id, quantity, unit_price)| OrderProduct {
+                    order_id: order.id,
+                    product_id,
+                    quantity,
+                    unit_price,
+                })
+                .collect();
+
+            diesel::insert_into(order_product::table)
+                .values(&order_lines)
+                .execute(conn)?;
+
+            Ok(order)
+        })
+    }
+
+    /// Stores the payment created with the payment provider on the order.
+    pub fn set_payment(conn: &mut PgConnection, order_id: Uuid, payment_id: &str, payment_url: Option<&str>) -> Result<Order, diesel::result::Error> {
+        diesel::update(orders::table.find(order_id))
+            .set((
+                orders::mollie_payment_id.eq(payment_id),
+                orders::mollie_payment_url.eq(payment_url),
+            ))
+            .get_result(conn)
+    }
+
+    pub fn set_status(conn: &mut PgConnection, order_id: Uuid, status: OrderStatus) -> Result<Order, diesel::result::Error> {
+        diesel::update(orders::table.find(order_id))
+            .set(orders::status.eq(status))
+            .get_result(conn)
+    }
+
+    /// Moves the order paid by `payment_id` to `status` if the state machine allows it.
+    /// The order row is locked so concurrent webhook deliveries are applied one at a time.
+    pub fn apply_payment_status(conn: &mut PgConnection, payment_id: &str, status: OrderStatus) -> Result<Option<StatusChange>, diesel::result::Error> {
+        conn.transaction(|conn| {
+            let order = orders::table
+                .filter(orders::mollie_payment_id.eq(payment_id))
+                .select(Order::as_select())
+                .for_update()
+                .first::<Order>(conn)
+                .optional()?;
+
+            let order = match order {
+                Some(order) => order,
+                None => return Ok(None),
+            };
+
+            if order.status == status {
+                return Ok(Some(StatusChange::Unchanged(order)));
+            }
+            if !order.status.can_transition_to(status) {
+                return Ok(Some(StatusChange::Rejected(order)));
+            }
+
+            let updated = Order::set_status(conn, order.id, status)?;
+            Ok(Some(StatusChange::Updated(updated)))
+        })
+    }
+
+    /// Returns the orders of a user, most recent first, with their lines translated in `locale`.
+    pub fn find_all_for_user(conn: &mut PgConnection, user_id: Uuid, locale: &str) -> Result<Vec<OrderWithProducts>, diesel::result::Error> {
+        let user_orders = orders::table
+            .filter(orders::user_id.eq(user_id))
+            .order(orders::created_at.desc())
+            .select(Order::as_select())
+            .load::<Order>(conn)?;
+
+        Self::with_products(conn, user_orders, locale)
+    }
+
+    /// Returns a single order of a user, or `None` if it doesn't exist or belongs to someone else.
+    pub fn find_for_user(conn: &mut PgConnection, user_id: Uuid, order_id: Uuid, locale: &str) -> Result<Option<OrderWithProducts>, diesel::result::Error> {
+        let user_order = orders::table
+            .filter(orders::id.eq(order_id))
+            .filter(orders::user_id.eq(user_id))
+            .select(Order::as_select())
+            .first::<Order>(conn)
+            .optional()?;
+
+        match user_order {
+            Some(order) => Ok(Self::with_products(conn, vec![order], locale)?.pop()),
+            None => Ok(None),
+        }
+    }
+
+    fn with_products(conn: &mut PgConnection, orders: Vec<Order>, locale: &str) -> Result<Vec<OrderWithProducts>, diesel::result::Error> {
+        let order_ids: Vec<Uuid> = orders.iter().map(|o| o.id).collect();
+
+        let raw_lines = order_product::table
+            .inner_join(products::table)
+            .left_join(product_translations::table.on(
+                product_translations::product_id.eq(order_product::product_id)
+                    .and(product_translations::locale.eq(locale))
+            ))
+            .filter(order_product::order_id.eq_any(&order_ids))
+            .select((
+                order_product::order_id,
+                order_product::product_id,
+                product_translations::name.nullable(),
+                products::code,
+                order_product::quantity,
+                order_product::unit_price,
+            ))
+            .load::<(Uuid, Uuid, Option<String>, Option<String>, i32, f64)>(conn)?;
+
+        let mut lines: HashMap<Uuid, Vec<OrderLine>> = HashMap::new();
+        for (order_id, product_id, name, code, quantity, unit_price) in raw_lines {
+            lines.entry(order_id).or_default().push(OrderLine {
+                product_id,
+                name,
+                code,
+                quantity,
+                unit_price,
+                total_price: unit_price * quantity as f64,
+            });
+        }
+
+        Ok(orders
+            .into_iter()
+            .map(|order| {
+                let mut products = lines.remove(&order.id).unwrap_or_default();
+                // Sort products by name
+                products.sort_by(|a, b| a.name.cmp(&b.name));
+                OrderWithProducts::new(order, products)
+            })
+            .collect())
+    }
+}
+
+impl OrderWithProducts {
+    pub fn new(order: Order, products: Vec<OrderLine>) -> Self {
+        let total_price = products.iter().map(|line| line.total_price).sum();
+
+        OrderWithProducts {
+            order,
+            products,
+            total_price,
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use crate::models::text_enum::text_enum;
+
+text_enum! {
+    /// Status of an order, stored as text and guarded by the `orders_status_check` constraint.
+    pub enum OrderStatus ("order status") {
+        Open => "OPEN",
+        Pending => "PENDING",
+        Authorized => "AUTHORIZED",
+        Paid => "PAID",
+        Failed => "FAILED",
+        Expired => "EXPIRED",
+        Canceled => "CANCELED",
+    }
+}
+
+impl OrderStatus {
+    /// Maps a Mollie payment status, which is the lowercase version of ours.
+    pub fn from_mollie(status: &str) -> Option<OrderStatus> {
+        status.to_uppercase().parse().ok()
+    }
+
+    /// Order status state machine: payments only move forward, and PAID, FAILED,
+    /// EXPIRED and CANCELED are final.
+    pub fn can_transition_to(self, next: OrderStatus) -> bool {
+        use OrderStatus::*;
+        match self {
+            Open => matches!(next, Pending | Authorized | Paid | Failed | Expired | Canceled),
+            Pending => matches!(next, Authorized | Paid | Failed | Expired | Canceled),
+            Authorized => matches!(next, Paid | Failed | Expired | Canceled),
+            Paid | Failed | Expired | Canceled => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OrderStatus::*;
+
+    #[test]
+    fn payments_move_forward() {
+        assert!(Open.can_transition_to(Pending));
+        assert!(Open.can_transition_to(Paid));
+        assert!(Pending.can_transition_to(Authorized));
+        assert!(Authorized.can_transition_to(Paid));
+        assert!(Authorized.can_transition_to(Canceled));
+    }
+
+    #[test]
+    fn payments_never_move_back() {
+        assert!(!Pending.can_transition_to(Open));
+        assert!(!Authorized.can_transition_to(Pending));
+        assert!(!Open.can_transition_to(Open));
+    }
+
+    #[test]
+    fn final_statuses_are_final() {
+        for status in [Paid, Failed, Expired, Canceled] {
+            for next in [Open, Pending, Authorized, Paid, Failed, Expired, Canceled] {
+                assert!(!status.can_transition_to(next), "{} -> {}", status, next);
+            }
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chrono::{NaiveDateTime, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use uuid::Uuid;
+use crate::config::Config;
+use crate::models::refresh_token::{hash_token, RefreshToken};
+use crate::schema::{password_reset_tokens, users};
+
+#[derive(Queryable, Selectable, Identifiable, Debug, Clone)]
+#[diesel(table_name = password_reset_tokens)]
+pub struct PasswordResetToken {
+    pub id: Uuid,
+    pub created_at: Option<NaiveDateTime>,
+    pub updated_at: Option<NaiveDateTime>,
+    pub user_id: Uuid,
+    pub token_hash: String,
+    pub expires_at: NaiveDateTime,
+    pub used_at: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = password_reset_tokens)]
+pub struct NewPasswordResetToken<'a> {
+    pub user_id: Uuid,
+    pub token_hash: &'a str,
+    pub expires_at: NaiveDateTime,
+}
+
+/// Random token sent by email, only its SHA-256 hash is stored.
+fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+impl PasswordResetToken {
+    /// Issues a reset token for the user. Tokens issued before are discarded,
+    /// so only the most recent email can be used.
+    pub fn issue(conn: &mut PgConnection, user_id: Uuid) -> Result<String, diesel::result::Error> {
+        let token = generate_token();
+
+        conn.transaction::<_, diesel::result::Error, _>(|conn| {
+            diesel::delete(password_reset_tokens::table.filter(password_reset_tokens::user_id.eq(user_id)))
+                .execute(conn)?;
+
+            diesel::insert_into(password_reset_tokens::table)
+                .values(&NewPasswordResetToken {
+                    user_id,
+                    token_hash: &hash_token(&token),
+                    expires_at: (Utc::now() + Config::global().tokens.password_reset_token_lifetime).naive_utc(),
+                })
+                .execute(conn)?;
+            Ok(())
+        })?;
+
+        Ok(token)
+    }
+
+    /// Whether the token is known, unused and not expired.
+    pub fn is_valid(conn: &mut PgConnection, token: &str) -> Result<bool, diesel::result::Error> {
+        let now = Utc::now().naive_utc();
+        let found = password_reset_tokens::table
+            .filter(password_reset_tokens::token_hash.eq(hash_token(token)))
+            .filter(password_reset_tokens::used_at.is_null())
+            .filter(password_reset_tokens::expires_at.gt(now))
+            .count()
+            .get_result::<i64>(conn)?;
+        Ok(found > 0)
+    }
+
+    /// Uses the token to replace the password of its user, and signs the user out of every session.
+    /// Returns false if the token is unknown, expired or already used.
+    pub fn reset_password(conn: &mut PgConnection, token: &str, password_hash: &str, salt: &str) -> Result<bool, diesel::result::Error> {
+        conn.transaction(|conn| {
+            let stored = password_reset_tokens::table
+                .filter(password_reset_tokens::token_hash.eq(hash_token(token)))
+                .select(PasswordResetToken::as_select())
+                .for_update()
+                .first::<PasswordResetToken>(conn)
+                .optional()?;
+
+            let now = Utc::now().naive_utc();
+            let stored = match stored {
+                Some(stored) if stored.used_at.is_none() && stored.expires_at > now => stored,
+                _ => return Ok(false),
+            };
+
+            diesel::update(password_reset_tokens::table.find(stored.id))
+                .set(password_reset_tokens::used_at.eq(now))
+                .execute(conn)?;
+
+            diesel::update(users::table.find(stored.user_id))
+                .set((users::password.eq(password_hash), users::salt.eq(salt)))
+                .execute(conn)?;
+
+            RefreshToken::revoke_all_for_user(conn, stored.user_id)?;
+            Ok(true)
+        })
+    }
+}
@@ -0,0 +1,10 @@
+use crate::models::text_enum::text_enum;
+
+text_enum! {
+    /// How an order is paid, stored as text and guarded by the `orders_payment_mode_check` constraint.
+    pub enum PaymentMode ("payment mode") {
+        Cash => "CASH",
+        Online => "ONLINE",
+        Terminal => "TERMINAL",
+    }
+}
@@ -4,8 +4,14 @@ use diesel::prelude::*;
 use uuid::Uuid;
 use std::error::Error;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use crate::schema::{product_categories, product_category_translations, product_product_category, product_translations, products};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use diesel::result::DatabaseErrorKind;
+use diesel::upsert::excluded;
+use crate::locale::supported_locales;
+use crate::schema::{attachments, order_product, product_categories, product_category_translations, product_product_category, product_translations, products};
+use crate::validation::{trimmed, validate_locale};
+use validator::Validate;
 
 #[derive(Serialize, Deserialize, Queryable, Selectable)]
 #[diesel(table_name = crate::schema::products)]
@@ -31,6 +37,139 @@ pub struct ProductTranslation {
     pub product_id: Uuid
 }
 
+#[derive(Insertable, AsChangeset)]
+#[diesel(table_name = crate::schema::products)]
+#[diesel(treat_none_as_null = true)]
+pub struct NewProduct<'a> {
+    pub price: Option<f64>,
+    pub is_active: bool,
+    pub code: Option<&'a str>,
+    pub slug: Option<&'a str>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::product_translations)]
+pub struct NewProductTranslation<'a> {
+    pub product_id: Uuid,
+    pub name: &'a str,
+    pub description: Option<&'a str>,
+    pub locale: &'a str,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::product_product_category)]
+pub struct NewProductCategoryLink {
+    pub product_id: Uuid,
+    pub product_category_id: Uuid,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct ProductTranslationForm {
+    #[validate(custom(function = "validate_locale"))]
+    pub locale: String,
+    #[serde(deserialize_with = "trimmed")]
+    #[validate(length(min = 1, max = 255, message = "Name must be between 1 and 255 characters long"))]
+    pub name: String,
+    pub description: Option<String>,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct ProductForm {
+    #[validate(range(min = 0.0, message = "Price can't be negative"))]
+    pub price: Option<f64>,
+    #[serde(default = "default_is_active")]
+    pub is_active: bool,
+    #[validate(length(min = 1, max = 255, message = "Code must be between 1 and 255 characters long"))]
+    pub code: Option<String>,
+    #[validate(length(min = 1, max = 255, message = "Slug must be between 1 and 255 characters long"))]
+    pub slug: Option<String>,
+    #[validate(nested)]
+    pub translations: Vec<ProductTranslationForm>,
+    #[serde(default)]
+    pub category_ids: Vec<Uuid>,
+}
+
+fn default_is_active() -> bool {
+    true
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ProductDetails {
+    #[serde(flatten)]
+    pub product: Product,
+    pub translations: Vec<ProductTranslation>,
+    pub category_ids: Vec<Uuid>,
+}
+
+#[derive(Debug)]
+pub enum ProductError {
+    NotFound,
+    InvalidTranslations(String),
+    UnknownCategory(Uuid),
+    DuplicateSlug,
+    /// Products that were ordered are kept for the order history, they can only be deactivated.
+    Ordered,
+    Database(diesel::result::Error),
+}
+
+impl fmt::Display for ProductError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProductError::NotFound => write!(f, "Product not found"),
+            ProductError::InvalidTranslations(reason) => write!(f, "Invalid translations: {}", reason),
+            ProductError::UnknownCategory(id) => write!(f, "Category {} does not exist", id),
+            ProductError::DuplicateSlug => write!(f, "A product with this slug already exists"),
+            ProductError::Ordered => write!(f, "Product has been ordered, deactivate it instead"),
+            ProductError::Database(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl Error for ProductError {}
+
+impl From<diesel::result::Error> for ProductError {
+    fn from(e: diesel::result::Error) -> Self {
+        match e {
+            diesel::result::Error::NotFound => ProductError::NotFound,
+            diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, ref info)
+                if info.constraint_name() == Some("products_slug_unique") => ProductError::DuplicateSlug,
+            e => ProductError::Database(e),
+        }
+    }
+}
+
+impl ProductForm {
+    /// Every supported locale must be translated exactly once, otherwise the
+    /// product would be missing from the menu in that language.
+    fn validate_translations(&self) -> Result<(), ProductError> {
+        let mut locales = HashSet::new();
+        for translation in &self.translations {
+            if !supported_locales().contains(&translation.locale) {
+                return Err(ProductError::InvalidTranslations(format!("unsupported locale {}", translation.locale)));
+            }
+            if !locales.insert(translation.locale.as_str()) {
+                return Err(ProductError::InvalidTranslations(format!("duplicated locale {}", translation.locale)));
+            }
+            if translation.name.trim().is_empty() {
+                return Err(ProductError::InvalidTranslations(format!("empty name for locale {}", translation.locale)));
+            }
+        }
+        if let Some(missing) = supported_locales().iter().find(|locale| !locales.contains(locale.as_str())) {
+            return Err(ProductError::InvalidTranslations(format!("missing locale {}", missing)));
+        }
+        Ok(())
+    }
+
+    fn as_new_product(&self) -> NewProduct<'_> {
+        NewProduct {
+            price: self.price,
+            is_active: self.is_active,
+            code: self.code.as_deref(),
+            slug: self.slug.as_deref(),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Queryable)]
 pub struct ProductInfo {
     pub id: Uuid,
@@ -50,7 +189,7 @@ pub struct CategoryWithProducts {
 }
 
 impl Product {
-    pub fn get_products_grouped_by_category(conn: &mut PgConnection, locale: &str, search_query: Option<&str>) -> Result<Vec<CategoryWithProducts>, Box<dyn Error + Send + Sync>> {
+    pub fn get_products_grouped_by_category(conn: &mut PgConnection, locale: &str, search_query: Option<&str>) -> Result<Vec<CategoryWithProducts>, diesel::result::Error> {
         let mut query = product_categories::table
             .inner_join(product_category_translations::table.on(product_categories::id.eq(product_category_translations::product_category_id)))
             .inner_join(product_product_category::table.on(product_categories::id.eq(product_product_category::product_category_id)))
@@ -110,4 +249,150 @@ impl Product {
 
         Ok(res)
     }
+
+    pub fn find_details(conn: &mut PgConnection, product_id: Uuid) -> Result<ProductDetails, ProductError> {
+        let product = products::table
+            .find(product_id)
+            .select(Product::as_select())
+            .first::<Product>(conn)?;
+
+        let translations = product_translations::table
+            .filter(product_translations::product_id.eq(product_id))
+            .order(product_translations::locale.asc())
+            .select(ProductTranslation::as_select())
+            .load::<ProductTranslation>(conn)?;
+
+        let category_ids = product_product_category::table
+            .filter(product_product_category::product_id.eq(product_id))
+            .select(product_product_category::product_category_id)
+            .load::<Uuid>(conn)?;
+
+        Ok(ProductDetails {
+            product,
+            translations,
+            category_ids,
+        })
+    }
+
+    /// Creates a product with its translations and categories in a single transaction.
+    pub fn create(conn: &mut PgConnection, form: &ProductForm) -> Result<ProductDetails, ProductError> {
+        form.validate_translations()?;
+
+        conn.transaction(|conn| {
+            let product_id = diesel::insert_into(products::table)
+                .values(&form.as_new_product())
+                .returning(products::id)
+                .get_result::<Uuid>(conn)?;
+
+            Self::save_translations(conn, product_id, form)?;
+            Self::save_categories(conn, product_id, &form.category_ids)?;
+
+            Self::find_details(conn, product_id)
+        })
+    }
+
+    /// Updates a product, replacing its translations and categories, in a single transaction.
+    pub fn update(conn: &mut PgConnection, product_id: Uuid, form: &ProductForm) -> Result<ProductDetails, ProductError> {
+        form.validate_translations()?;
+
+        conn.transaction(|conn| {
+            diesel::update(products::table.find(product_id))
+                .set(&form.as_new_product())
+                .returning(products::id)
+                .get_result::<Uuid>(conn)?;
+
+            Self::save_translations(conn, product_id, form)?;
+            diesel::delete(product_product_category::table.filter(product_product_category::product_id.eq(product_id)))
+                .execute(conn)?;
+            Self::save_categories(conn, product_id, &form.category_ids)?;
+
+            Self::find_details(conn, product_id)
+        })
+    }
+
+    pub fn set_active(conn: &mut PgConnection, product_id: Uuid, active: bool) -> Result<ProductDetails, ProductError> {
+        diesel::update(products::table.find(product_id))
+            .set(products::is_active.eq(active))
+            .returning(products::id)
+            .get_result::<Uuid>(conn)?;
+
+        Self::find_details(conn, product_id)
+    }
+
+    /// Deletes a product that was never ordered, along with its attachments.
+    /// Translations and category links are removed by the foreign key cascades.
+    pub fn delete(conn: &mut PgConnection, product_id: Uuid) -> Result<(), ProductError> {
+        conn.transaction(|conn| {
+            let ordered = order_product::table
+                .filter(order_product::product_id.eq(product_id))
+                .count()
+                .get_result::<i64>(conn)?;
+            if ordered > 0 {
+                return Err(ProductError::Ordered);
+            }
+
+            diesel::delete(attachments::table.filter(attachments::product_id.eq(product_id))).execute(conn)?;
+            let deleted = diesel::delete(products::table.find(product_id)).execute(conn)?;
+            if deleted == 0 {
+                return Err(ProductError::NotFound);
+            }
+            Ok(())
+        })
+    }
+
+    fn save_translations(conn: &mut PgConnection, product_id: Uuid, form: &ProductForm) -> Result<(), ProductError> {
+        let translations: Vec<NewProductTranslation> = form
+            .translations
+            .iter()
+            .map(|t| NewProductTranslation {
+                product_id,
+                name: &t.name,
+                description: t.description.as_deref(),
+                locale: &t.locale,
+            })
+            .collect();
+
+        diesel::insert_into(product_translations::table)
+            .values(&translations)
+            .on_conflict((product_translations::product_id, product_translations::locale))
+            .do_update()
+            .set((
+                product_translations::name.eq(excluded(product_translations::name)),
+                product_translations::description.eq(excluded(product_translations::description)),
+            ))
+            .execute(conn)?;
+        Ok(())
+    }
+
+    fn save_categories(conn: &mut PgConnection, product_id: Uuid, category_ids: &[Uuid]) -> Result<(), ProductError> {
+        let category_ids: Vec<Uuid> = category_ids
+            .iter()
+            .copied()
+            .collect::<HashSet<Uuid>>()
+            .into_iter()
+            .collect();
+
+        let existing: HashSet<Uuid> = product_categories::table
+            .filter(product_categories::id.eq_any(&category_ids))
+            .select(product_categories::id)
+            .load::<Uuid>(conn)?
+            .into_iter()
+            .collect();
+        if let Some(unknown) = category_ids.iter().find(|id| !existing.contains(id)) {
+            return Err(ProductError::UnknownCategory(*unknown));
+        }
+
+        let links: Vec<NewProductCategoryLink> = category_ids
+            .into_iter()
+            .map(|product_category_id| NewProductCategoryLink {
+                product_id,
+                product_category_id,
+            })
+            .collect();
+
+        diesel::insert_into(product_product_category::table)
+            .values(&links)
+            .execute(conn)?;
+        Ok(())
+    }
 }
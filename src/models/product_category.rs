@@ -0,0 +1,287 @@
+use chrono::NaiveDateTime;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::upsert::excluded;
+use uuid::Uuid;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use crate::locale::supported_locales;
+use crate::schema::{product_categories, product_category_translations};
+use crate::validation::{trimmed, validate_locale};
+use validator::Validate;
+
+#[derive(Serialize, Deserialize, Queryable, Selectable, Debug, Clone)]
+#[diesel(table_name = product_categories)]
+pub struct ProductCategory {
+    pub id: Uuid,
+    pub created_at: Option<NaiveDateTime>,
+    pub updated_at: Option<NaiveDateTime>,
+    pub order: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Queryable, Selectable, Debug, Clone)]
+#[diesel(table_name = product_category_translations)]
+pub struct ProductCategoryTranslation {
+    pub id: Uuid,
+    pub created_at: Option<NaiveDateTime>,
+    pub updated_at: Option<NaiveDateTime>,
+    pub product_category_id: Uuid,
+    pub name: String,
+    pub locale: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = product_category_translations)]
+pub struct NewProductCategoryTranslation<'a> {
+    pub product_category_id: Uuid,
+    pub name: &'a str,
+    pub locale: &'a str,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct CategoryTranslationForm {
+    #[validate(custom(function = "validate_locale"))]
+    pub locale: String,
+    #[serde(deserialize_with = "trimmed")]
+    #[validate(length(min = 1, max = 255, message = "Name must be between 1 and 255 characters long"))]
+    pub name: String,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct NewCategoryForm {
+    #[validate(range(min = 0, message = "Order can't be negative"))]
+    pub order: Option<i32>,
+    #[validate(nested)]
+    pub translations: Vec<CategoryTranslationForm>,
+}
+
+/// Partial update: only the given fields and locales are changed.
+#[derive(Deserialize, Validate)]
+pub struct UpdateCategoryForm {
+    #[validate(range(min = 0, message = "Order can't be negative"))]
+    pub order: Option<i32>,
+    #[serde(default)]
+    #[validate(nested)]
+    pub translations: Vec<CategoryTranslationForm>,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct ReorderCategoriesForm {
+    #[validate(length(min = 1, message = "Every category must be listed"))]
+    pub category_ids: Vec<Uuid>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CategoryDetails {
+    #[serde(flatten)]
+    pub category: ProductCategory,
+    pub translations: Vec<ProductCategoryTranslation>,
+}
+
+#[derive(Debug)]
+pub enum CategoryError {
+    NotFound,
+    InvalidTranslations(String),
+    InvalidOrder(String),
+    Database(diesel::result::Error),
+}
+
+impl fmt::Display for CategoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CategoryError::NotFound => write!(f, "Category not found"),
+            CategoryError::InvalidTranslations(reason) => write!(f, "Invalid translations: {}", reason),
+            CategoryError::InvalidOrder(reason) => write!(f, "Invalid order: {}", reason),
+            CategoryError::Database(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl Error for CategoryError {}
+
+impl From<diesel::result::Error> for CategoryError {
+    fn from(e: diesel::result::Error) -> Self {
+        match e {
+            diesel::result::Error::NotFound => CategoryError::NotFound,
+            e => CategoryError::Database(e),
+        }
+    }
+}
+
+/// Checks locales are supported and unique, and that every supported locale is present if `complete`.
+fn validate_translations(translations: &[CategoryTranslationForm], complete: bool) -> Result<(), CategoryError> {
+    let mut locales = HashSet::new();
+    for translation in translations {
+        if !supported_locales().contains(&translation.locale) {
+            return Err(CategoryError::InvalidTranslations(format!("unsupported locale {}", translation.locale)));
+        }
+        if !locales.insert(translation.locale.as_str()) {
+            return Err(CategoryError::InvalidTranslations(format!("duplicated locale {}", translation.locale)));
+        }
+        if translation.name.trim().is_empty() {
+            return Err(CategoryError::InvalidTranslations(format!("empty name for locale {}", translation.locale)));
+        }
+    }
+    if complete {
+        if let Some(missing) = supported_locales().iter().find(|locale| !locales.contains(locale.as_str())) {
+            return Err(CategoryError::InvalidTranslations(format!("missing locale {}", missing)));
+        }
+    }
+    Ok(())
+}
+
+impl ProductCategory {
+    /// Returns every category in menu order with all of its translations.
+    pub fn find_all_details(conn: &mut PgConnection) -> Result<Vec<CategoryDetails>, diesel::result::Error> {
+        let categories = product_categories::table
+            .order((product_categories::order.asc().nulls_last(), product_categories::created_at.asc()))
+            .select(ProductCategory::as_select())
+            .load::<ProductCategory>(conn)?;
+
+        let mut translations: HashMap<Uuid, Vec<ProductCategoryTranslation>> = HashMap::new();
+        for translation in product_category_translations::table
+            .order(product_category_translations::locale.asc())
+            .select(ProductCategoryTranslation::as_select())
+            .load::<ProductCategoryTranslation>(conn)?
+        {
+            translations.entry(translation.product_category_id).or_default().push(translation);
+        }
+
+        Ok(categories
+            .into_iter()
+            .map(|category| CategoryDetails {
+                translations: translations.remove(&category.id).unwrap_or_default(),
+                category,
+            })
+            .collect())
+    }
+
+    pub fn find_details(conn: &mut PgConnection, category_id: Uuid) -> Result<CategoryDetails, CategoryError> {
+        let category = product_categories::table
+            .find(category_id)
+            .select(ProductCategory::as_select())
+            .first::<ProductCategory>(conn)?;
+
+        let translations = product_category_translations::table
+            .filter(product_category_translations::product_category_id.eq(category_id))
+            .order(product_category_translations::locale.asc())
+            .select(ProductCategoryTranslation::as_select())
+            .load::<ProductCategoryTranslation>(conn)?;
+
+        Ok(CategoryDetails { category, translations })
+    }
+
+    /// Creates a category, placed last in the menu unless an order is given.
+    pub fn create(conn: &mut PgConnection, form: &NewCategoryForm) -> Result<CategoryDetails, CategoryError> {
+        validate_translations(&form.translations, true)?;
+
+        conn.transaction(|conn| {
+            let order = match form.order {
+                Some(order) => order,
+                None => product_categories::table
+                    .select(diesel::dsl::max(product_categories::order))
+                    .first::<Option<i32>>(conn)?
+                    .map_or(1, |max| max + 1),
+            };
+
+            let category_id = diesel::insert_into(product_categories::table)
+                .values(product_categories::order.eq(order))
+                .returning(product_categories::id)
+                .get_result::<Uuid>(conn)?;
+
+            Self::save_translations(conn, category_id, &form.translations)?;
+
+            Self::find_details(conn, category_id)
+        })
+    }
+
+    /// Renames the category in the given locales and/or moves it.
+    pub fn update(conn: &mut PgConnection, category_id: Uuid, form: &UpdateCategoryForm) -> Result<CategoryDetails, CategoryError> {
+        validate_translations(&form.translations, false)?;
+
+        conn.transaction(|conn| {
+            // Also makes sure the category exists before touching its translations
+            let category = product_categories::table
+                .find(category_id)
+                .select(ProductCategory::as_select())
+                .for_update()
+                .first::<ProductCategory>(conn)?;
+
+            if let Some(order) = form.order {
+                diesel::update(product_categories::table.find(category.id))
+                    .set(product_categories::order.eq(order))
+                    .execute(conn)?;
+            }
+            Self::save_translations(conn, category.id, &form.translations)?;
+
+            Self::find_details(conn, category.id)
+        })
+    }
+
+    /// Sets the menu order from the full ordered list of category ids.
+    pub fn reorder(conn: &mut PgConnection, category_ids: &[Uuid]) -> Result<Vec<CategoryDetails>, CategoryError> {
+        let unique: HashSet<&Uuid> = category_ids.iter().collect();
+        if unique.len() != category_ids.len() {
+            return Err(CategoryError::InvalidOrder("duplicated category".to_string()));
+        }
+
+        conn.transaction(|conn| {
+            let existing: HashSet<Uuid> = product_categories::table
+                .select(product_categories::id)
+                .for_update()
+                .load::<Uuid>(conn)?
+                .into_iter()
+                .collect();
+
+            if let Some(unknown) = category_ids.iter().find(|id| !existing.contains(id)) {
+                return Err(CategoryError::InvalidOrder(format!("category {} does not exist", unknown)));
+            }
+            if existing.len() != category_ids.len() {
+                return Err(CategoryError::InvalidOrder("every category must be listed".to_string()));
+            }
+
+            for (position, category_id) in category_ids.iter().enumerate() {
+                diesel::update(product_categories::table.find(category_id))
+                    .set(product_categories::order.eq(position as i32 + 1))
+                    .execute(conn)?;
+            }
+
+            Ok(Self::find_all_details(conn)?)
+        })
+    }
+
+    /// Deletes a category. Its translations and product links are removed by the
+    /// foreign key cascades; the products themselves are kept.
+    pub fn delete(conn: &mut PgConnection, category_id: Uuid) -> Result<(), CategoryError> {
+        let deleted = diesel::delete(product_categories::table.find(category_id)).execute(conn)?;
+        if deleted == 0 {
+            return Err(CategoryError::NotFound);
+        }
+        Ok(())
+    }
+
+    fn save_translations(conn: &mut PgConnection, category_id: Uuid, translations: &[CategoryTranslationForm]) -> Result<(), CategoryError> {
+        if translations.is_empty() {
+            return Ok(());
+        }
+
+        let translations: Vec<NewProductCategoryTranslation> = translations
+            .iter()
+            .map(|t| NewProductCategoryTranslation {
+                product_category_id: category_id,
+                name: &t.name,
+                locale: &t.locale,
+            })
+            .collect();
+
+        diesel::insert_into(product_category_translations::table)
+            .values(&translations)
+            .on_conflict((product_category_translations::product_category_id, product_category_translations::locale))
+            .do_update()
+            .set(product_category_translations::name.eq(excluded(product_category_translations::name)))
+            .execute(conn)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,210 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use uuid::Uuid;
+use crate::models::revoked_access_token::RevokedAccessToken;
+use crate::config::Config;
+use crate::models::user::{access_token_lifetime, Claims, TokenType, User};
+use crate::schema::refresh_tokens;
+
+#[derive(Queryable, Selectable, Identifiable, Debug, Clone)]
+#[diesel(table_name = refresh_tokens)]
+pub struct RefreshToken {
+    pub id: Uuid,
+    pub created_at: Option<NaiveDateTime>,
+    pub updated_at: Option<NaiveDateTime>,
+    pub user_id: Uuid,
+    pub family_id: Uuid,
+    pub token_hash: String,
+    pub expires_at: NaiveDateTime,
+    pub used_at: Option<NaiveDateTime>,
+    pub revoked_at: Option<NaiveDateTime>,
+    pub access_token_jti: Option<Uuid>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = refresh_tokens)]
+pub struct NewRefreshToken<'a> {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub family_id: Uuid,
+    pub token_hash: &'a str,
+    pub expires_at: NaiveDateTime,
+    pub access_token_jti: Uuid,
+}
+
+#[derive(Debug)]
+pub enum TokenError {
+    Jwt(jsonwebtoken::errors::Error),
+    Database(diesel::result::Error),
+}
+
+impl fmt::Display for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenError::Jwt(e) => write!(f, "Token error: {}", e),
+            TokenError::Database(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for TokenError {}
+
+impl From<jsonwebtoken::errors::Error> for TokenError {
+    fn from(e: jsonwebtoken::errors::Error) -> Self {
+        TokenError::Jwt(e)
+    }
+}
+
+impl From<diesel::result::Error> for TokenError {
+    fn from(e: diesel::result::Error) -> Self {
+        TokenError::Database(e)
+    }
+}
+
+/// Outcome of presenting a refresh token.
+pub enum Rotation {
+    /// The token was valid and has been replaced by `token`.
+    Rotated { user: User, token: String },
+    /// The token was already used: it may have been stolen, so its whole family is revoked.
+    Reused,
+    Invalid,
+}
+
+/// Tokens are stored as SHA-256 hashes: they are signed and unique (their `jti`
+/// is the row id), so a slow password hash isn't needed and the hash can be looked up directly.
+pub(crate) fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+impl RefreshToken {
+    /// Issues a refresh token for the user, starting a new family (i.e. a new session) if none is given.
+    /// `access_token_jti` identifies the access token issued along with it, so it can be revoked with the session.
+    pub fn issue(conn: &mut PgConnection, user: &User, family_id: Option<Uuid>, access_token_jti: Uuid) -> Result<String, TokenError> {
+        let id = Uuid::new_v4();
+        let claims = Claims::new(user, TokenType::Refresh, id, Config::global().tokens.refresh_token_lifetime);
+        let token = claims.encode(&Config::global().jwt_secret)?;
+        let expires_at = DateTime::from_timestamp(claims.exp as i64, 0)
+            .unwrap_or_else(Utc::now)
+            .naive_utc();
+
+        diesel::insert_into(refresh_tokens::table)
+            .values(&NewRefreshToken {
+                id,
+                user_id: user.id,
+                family_id: family_id.unwrap_or_else(Uuid::new_v4),
+                token_hash: &hash_token(&token),
+                expires_at,
+                access_token_jti,
+            })
+            .execute(conn)?;
+
+        Ok(token)
+    }
+
+    /// Exchanges a refresh token for a new one of the same family. Each token can only be used once.
+    /// The token signature and type must have been checked with `Claims::decode` beforehand.
+    pub fn rotate(conn: &mut PgConnection, token: &str, access_token_jti: Uuid) -> Result<Rotation, TokenError> {
+        conn.transaction(|conn| {
+            let stored = refresh_tokens::table
+                .filter(refresh_tokens::token_hash.eq(hash_token(token)))
+                .select(RefreshToken::as_select())
+                .for_update()
+                .first::<RefreshToken>(conn)
+                .optional()?;
+
+            let stored = match stored {
+                Some(stored) => stored,
+                None => return Ok(Rotation::Invalid),
+            };
+            let now = Utc::now().naive_utc();
+
+            if stored.revoked_at.is_some() || stored.expires_at <= now {
+                return Ok(Rotation::Invalid);
+            }
+            if stored.used_at.is_some() {
+                Self::revoke_family(conn, stored.family_id)?;
+                return Ok(Rotation::Reused);
+            }
+
+            diesel::update(refresh_tokens::table.find(stored.id))
+                .set(refresh_tokens::used_at.eq(now))
+                .execute(conn)?;
+            // Reload the user so role changes apply to the new tokens
+            let user = User::find_by_id(conn, stored.user_id)?;
+            let token = Self::issue(conn, &user, Some(stored.family_id), access_token_jti)?;
+
+            Ok(Rotation::Rotated { user, token })
+        })
+    }
+
+    /// Revokes the session the refresh token belongs to. Returns false if the token is unknown.
+    pub fn revoke_session(conn: &mut PgConnection, token: &str) -> Result<bool, diesel::result::Error> {
+        conn.transaction(|conn| {
+            let family_id = refresh_tokens::table
+                .filter(refresh_tokens::token_hash.eq(hash_token(token)))
+                .select(refresh_tokens::family_id)
+                .first::<Uuid>(conn)
+                .optional()?;
+
+            match family_id {
+                Some(family_id) => {
+                    Self::revoke_family(conn, family_id)?;
+                    Ok(true)
+                }
+                None => Ok(false),
+            }
+        })
+    }
+
+    /// Revokes every session of the user.
+    pub fn revoke_all_for_user(conn: &mut PgConnection, user_id: Uuid) -> Result<(), diesel::result::Error> {
+        conn.transaction(|conn| {
+            let family_ids = refresh_tokens::table
+                .filter(refresh_tokens::user_id.eq(user_id))
+                .filter(refresh_tokens::revoked_at.is_null())
+                .select(refresh_tokens::family_id)
+                .distinct()
+                .load::<Uuid>(conn)?;
+
+            for family_id in family_ids {
+                Self::revoke_family(conn, family_id)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Revokes every token of the family, including the access tokens issued
+    /// with them that may not have expired yet.
+    pub fn revoke_family(conn: &mut PgConnection, family_id: Uuid) -> Result<usize, diesel::result::Error> {
+        let now = Utc::now().naive_utc();
+
+        // An access token lives at most its lifetime after its refresh token was used
+        let access_token_lifetime = access_token_lifetime();
+        let live_access_tokens = refresh_tokens::table
+            .filter(refresh_tokens::family_id.eq(family_id))
+            .filter(
+                refresh_tokens::used_at.is_null()
+                    .or(refresh_tokens::used_at.gt(now - access_token_lifetime)),
+            )
+            .select(refresh_tokens::access_token_jti)
+            .load::<Option<Uuid>>(conn)?
+            .into_iter()
+            .flatten()
+            .collect::<Vec<Uuid>>();
+        RevokedAccessToken::revoke(conn, &live_access_tokens, now + access_token_lifetime)?;
+
+        diesel::update(
+            refresh_tokens::table
+                .filter(refresh_tokens::family_id.eq(family_id))
+                .filter(refresh_tokens::revoked_at.is_null()),
+        )
+        .set(refresh_tokens::revoked_at.eq(now))
+        .execute(conn)
+    }
+}
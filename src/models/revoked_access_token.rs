@@ -0,0 +1,44 @@
+use chrono::{NaiveDateTime, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use uuid::Uuid;
+use crate::schema::revoked_access_tokens;
+
+/// Deny-list of access tokens revoked before they expired, by `jti`.
+/// Entries are only kept until the token would have expired anyway.
+#[derive(Insertable)]
+#[diesel(table_name = revoked_access_tokens)]
+pub struct RevokedAccessToken {
+    pub jti: Uuid,
+    pub expires_at: NaiveDateTime,
+}
+
+impl RevokedAccessToken {
+    pub fn revoke(conn: &mut PgConnection, jtis: &[Uuid], expires_at: NaiveDateTime) -> Result<(), diesel::result::Error> {
+        Self::purge_expired(conn)?;
+        if jtis.is_empty() {
+            return Ok(());
+        }
+
+        let entries: Vec<RevokedAccessToken> = jtis
+            .iter()
+            .map(|jti| RevokedAccessToken { jti: *jti, expires_at })
+            .collect();
+
+        diesel::insert_into(revoked_access_tokens::table)
+            .values(&entries)
+            .on_conflict_do_nothing()
+            .execute(conn)?;
+        Ok(())
+    }
+
+    pub fn is_revoked(conn: &mut PgConnection, jti: Uuid) -> Result<bool, diesel::result::Error> {
+        diesel::select(diesel::dsl::exists(revoked_access_tokens::table.find(jti)))
+            .get_result(conn)
+    }
+
+    fn purge_expired(conn: &mut PgConnection) -> Result<usize, diesel::result::Error> {
+        diesel::delete(revoked_access_tokens::table.filter(revoked_access_tokens::expires_at.lt(Utc::now().naive_utc())))
+            .execute(conn)
+    }
+}
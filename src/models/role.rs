@@ -0,0 +1,13 @@
+use crate::models::text_enum::text_enum;
+
+text_enum! {
+    /// Role of a user, stored as text and guarded by the `users_role_check` constraint.
+    /// Roles are ordered: staff can do everything a customer can, and admins everything staff can.
+    #[derive(PartialOrd, Ord, Default)]
+    pub enum Role ("role") {
+        #[default]
+        Customer => "CUSTOMER",
+        Staff => "STAFF",
+        Admin => "ADMIN",
+    }
+}
@@ -2,11 +2,21 @@
 
 use diesel::prelude::*;
 use diesel::pg::PgConnection;
+use diesel::result::DatabaseErrorKind;
+use crate::config::Config;
+use crate::models::refresh_token::RefreshToken;
+use crate::models::role::Role;
 use crate::schema::users;
+use crate::validation::{normalize_email, normalized_email, trimmed, validate_password};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
-use chrono::NaiveDateTime;
-use std::env;
+use chrono::{Duration, NaiveDateTime, Utc};
+use jsonwebtoken::errors::ErrorKind;
+use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use std::fmt;
+use validator::Validate;
+
+define_sql_function!(fn lower(x: diesel::sql_types::Text) -> diesel::sql_types::Text);
 
 #[derive(Serialize, Deserialize, Queryable, Identifiable, Debug, Clone)]
 #[diesel(table_name = users)]
@@ -17,15 +27,24 @@ pub struct User {
     pub name: String,
     pub email: String,
     pub email_verified_at: Option<NaiveDateTime>,
+    #[serde(skip_serializing)]
     pub password: String,
+    #[serde(skip_serializing)]
     pub salt: String,
+    #[serde(skip_serializing)]
     pub remember_token: Option<String>,
+    pub role: Role,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Validate)]
 pub struct UserForm {
+    #[serde(deserialize_with = "trimmed")]
+    #[validate(length(min = 1, max = 255, message = "Name must be between 1 and 255 characters long"))]
     pub name: String,
+    #[serde(deserialize_with = "normalized_email")]
+    #[validate(email(message = "Email must be a valid email address"))]
     pub email: String,
+    #[validate(custom(function = "validate_password"))]
     pub password: String,
 }
 
@@ -38,17 +57,153 @@ pub struct NewUser<'a> {
     pub salt: &'a str,
 }
 
-#[derive(Deserialize)]
+/// What other users and the user themselves get to see of an account.
+#[derive(Serialize)]
+pub struct UserProfile {
+    pub id: Uuid,
+    pub name: String,
+    pub email: String,
+    pub created_at: Option<NaiveDateTime>,
+}
+
+impl From<User> for UserProfile {
+    fn from(user: User) -> Self {
+        UserProfile {
+            id: user.id,
+            name: user.name,
+            email: user.email,
+            created_at: user.created_at,
+        }
+    }
+}
 
+#[derive(Debug)]
+pub enum UserError {
+    EmailTaken,
+    Database(diesel::result::Error),
+}
+
+impl fmt::Display for UserError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UserError::EmailTaken => write!(f, "An account already exists for this email"),
+            UserError::Database(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for UserError {}
+
+impl From<diesel::result::Error> for UserError {
+    fn from(e: diesel::result::Error) -> Self {
+        match e {
+            diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, ref info)
+                if matches!(info.constraint_name(), Some("users_email_unique") | Some("users_email_lower_unique")) => UserError::EmailTaken,
+            e => UserError::Database(e),
+        }
+    }
+}
+
+#[derive(Deserialize, Validate)]
 pub struct UserConnectionForm {
+    #[serde(deserialize_with = "normalized_email")]
+    #[validate(length(min = 1, message = "Email is required"))]
+    pub email: String,
+    #[validate(length(min = 1, message = "Password is required"))]
+    pub password: String,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct VerifyEmailForm {
+    #[validate(length(min = 1, message = "Token is required"))]
+    pub token: String,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct ForgotPasswordForm {
+    #[serde(deserialize_with = "normalized_email")]
+    #[validate(email(message = "Email must be a valid email address"))]
     pub email: String,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct ResetPasswordForm {
+    #[validate(length(min = 1, message = "Token is required"))]
+    pub token: String,
+    #[validate(custom(function = "validate_password"))]
     pub password: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Deserialize, Validate)]
+pub struct RoleForm {
+    pub role: Role,
+}
+
+/// Issuer and audience of the tokens, checked when decoding them.
+pub const TOKEN_ISSUER: &str = "tsb";
+pub const TOKEN_AUDIENCE: &str = "tsb-api";
+
+/// How long access tokens are valid, 15 minutes by default.
+pub fn access_token_lifetime() -> Duration {
+    Config::global().tokens.access_token_lifetime
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenType {
+    Access,
+    Refresh,
+    #[serde(rename = "email_verification")]
+    EmailVerification,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
-    pub sub: String,  // subject (the user's email in this case)
-    pub exp: usize,   // expiration time as a UNIX timestamp
+    pub sub: String,     // subject (the user's email in this case)
+    pub uid: Uuid,       // id of the user
+    pub typ: TokenType,  // access tokens authenticate requests, refresh tokens only get new tokens
+    pub iat: usize,      // issue time as a UNIX timestamp
+    pub exp: usize,      // expiration time as a UNIX timestamp
+    pub iss: String,     // issuer
+    pub aud: String,     // audience
+    pub jti: Uuid,       // unique id of the token
+    pub role: Role,      // role of the user when the token was issued
+}
+
+impl Claims {
+    pub fn new(user: &User, typ: TokenType, jti: Uuid, lifetime: Duration) -> Claims {
+        let now = Utc::now();
+        Claims {
+            sub: user.email.clone(),
+            uid: user.id,
+            typ,
+            iat: now.timestamp() as usize,
+            exp: (now + lifetime).timestamp() as usize,
+            iss: TOKEN_ISSUER.to_string(),
+            aud: TOKEN_AUDIENCE.to_string(),
+            jti,
+            role: user.role,
+        }
+    }
+
+    /// Signs the claims with `secret_key`, the application's being `Config::global().jwt_secret`.
+    pub fn encode(&self, secret_key: &str) -> Result<String, jsonwebtoken::errors::Error> {
+        encode(&Header::new(Algorithm::HS256), self, &EncodingKey::from_secret(secret_key.as_ref()))
+    }
+
+    /// Decodes and validates a token signed with `secret_key`, rejecting tokens of another type.
+    pub fn decode(token: &str, expected_type: TokenType, secret_key: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.set_issuer(&[TOKEN_ISSUER]);
+        validation.set_audience(&[TOKEN_AUDIENCE]);
+        validation.set_required_spec_claims(&["exp", "iat", "iss", "aud", "sub"]);
+
+        let claims = decode::<Claims>(token, &DecodingKey::from_secret(secret_key.as_ref()), &validation)?.claims;
+        if claims.typ != expected_type {
+            return Err(ErrorKind::InvalidToken.into());
+        }
+        Ok(claims)
+    }
 }
 
 impl User {
@@ -60,11 +215,126 @@ impl User {
 
         Ok(results)
     }
-}
 
-pub fn get_secret_key() -> String {
-    env::var("JWT_SECRET").expect("JWT_SECRET must be set")
+    pub fn create(connection: &mut PgConnection, new_user: &NewUser) -> Result<User, UserError> {
+        Ok(diesel::insert_into(users::table)
+            .values(new_user)
+            .get_result::<User>(connection)?)
+    }
+
+    /// Finds a user by email, ignoring the case of addresses stored before they were normalized.
+    pub fn find_by_email(connection: &mut PgConnection, user_email: &str) -> Result<User, diesel::result::Error> {
+        use crate::schema::users::dsl::*;
+        users
+            .filter(lower(email).eq(normalize_email(user_email)))
+            .first::<User>(connection)
+    }
+
+    pub fn find_by_id(connection: &mut PgConnection, user_id: Uuid) -> Result<User, diesel::result::Error> {
+        use crate::schema::users::dsl::*;
+        users
+            .find(user_id)
+            .first::<User>(connection)
+    }
+
+    /// Signed token proving the ownership of the user's email address. It's bound to the
+    /// address, so it can't verify another one if the email is changed in between.
+    pub fn email_verification_token(&self) -> Result<String, jsonwebtoken::errors::Error> {
+        Claims::new(self, TokenType::EmailVerification, Uuid::new_v4(), Config::global().tokens.email_verification_token_lifetime)
+            .encode(&Config::global().jwt_secret)
+    }
+
+    pub fn is_email_verified(&self) -> bool {
+        self.email_verified_at.is_some()
+    }
+
+    /// Marks the email address as verified, keeping the first verification date.
+    pub fn mark_email_verified(connection: &mut PgConnection, user_id: Uuid) -> Result<User, diesel::result::Error> {
+        use crate::schema::users::dsl::*;
+        diesel::update(users.find(user_id).filter(email_verified_at.is_null()))
+            .set(email_verified_at.eq(diesel::dsl::now.nullable()))
+            .execute(connection)?;
+        User::find_by_id(connection, user_id)
+    }
+
+    /// Changes the role of a user. Demoted users lose their sessions, so they can't keep
+    /// refreshing access tokens carrying the old role.
+    pub fn set_role(connection: &mut PgConnection, user_id: Uuid, new_role: Role) -> Result<User, diesel::result::Error> {
+        use crate::schema::users::dsl::*;
+        connection.transaction(|connection| {
+            let previous_role = users.find(user_id).select(role).for_update().first::<Role>(connection)?;
+            let user = diesel::update(users.find(user_id))
+                .set(role.eq(new_role))
+                .get_result::<User>(connection)?;
+
+            if new_role < previous_role {
+                RefreshToken::revoke_all_for_user(connection, user_id)?;
+            }
+            Ok(user)
+        })
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "0123456789abcdef0123456789abcdef";
+
+    fn user() -> User {
+        User {
+            id: Uuid::new_v4(),
+            created_at: None,
+            updated_at: None,
+            name: "Test".to_string(),
+            email: "test@example.com".to_string(),
+            email_verified_at: None,
+            password: String::new(),
+            salt: String::new(),
+            remember_token: None,
+            role: Role::Customer,
+        }
+    }
+
+    fn token(typ: TokenType, lifetime: Duration) -> String {
+        Claims::new(&user(), typ, Uuid::new_v4(), lifetime).encode(SECRET).unwrap()
+    }
+
+    #[test]
+    fn tokens_decode_as_their_own_type() {
+        let token = token(TokenType::Access, Duration::minutes(15));
+        let claims = Claims::decode(&token, TokenType::Access, SECRET).unwrap();
+        assert_eq!(claims.typ, TokenType::Access);
+        assert_eq!(claims.sub, "test@example.com");
+    }
+
+    #[test]
+    fn tokens_of_another_type_are_rejected() {
+        let refresh_token = token(TokenType::Refresh, Duration::days(7));
+        assert!(Claims::decode(&refresh_token, TokenType::Access, SECRET).is_err());
 
+        let access_token = token(TokenType::Access, Duration::minutes(15));
+        assert!(Claims::decode(&access_token, TokenType::Refresh, SECRET).is_err());
+        assert!(Claims::decode(&access_token, TokenType::EmailVerification, SECRET).is_err());
+    }
 
+    #[test]
+    fn tokens_for_another_issuer_or_audience_are_rejected() {
+        let mut claims = Claims::new(&user(), TokenType::Access, Uuid::new_v4(), Duration::minutes(15));
+        claims.aud = "another-api".to_string();
+        assert!(Claims::decode(&claims.encode(SECRET).unwrap(), TokenType::Access, SECRET).is_err());
+
+        let mut claims = Claims::new(&user(), TokenType::Access, Uuid::new_v4(), Duration::minutes(15));
+        claims.iss = "another-issuer".to_string();
+        assert!(Claims::decode(&claims.encode(SECRET).unwrap(), TokenType::Access, SECRET).is_err());
+    }
+
+    #[test]
+    fn expired_tokens_and_other_keys_are_rejected() {
+        let expired = token(TokenType::Access, -Duration::minutes(5));
+        assert!(Claims::decode(&expired, TokenType::Access, SECRET).is_err());
+
+        let token = token(TokenType::Access, Duration::minutes(15));
+        assert!(Claims::decode(&token, TokenType::Access, "another secret of thirty-two chars").is_err());
+    }
+}
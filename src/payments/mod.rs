@@ -0,0 +1,61 @@
+pub mod mollie;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use uuid::Uuid;
+
+pub use self::mollie::MollieClient;
+
+/// A payment to create with the payment provider.
+#[derive(Debug, Clone)]
+pub struct PaymentRequest {
+    pub order_id: Uuid,
+    pub amount: f64,
+    pub description: String,
+}
+
+/// A payment as known by the payment provider.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Payment {
+    pub id: String,
+    pub status: String,
+    pub checkout_url: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum PaymentError {
+    Http(reqwest::Error),
+    Api { status: u16, detail: String },
+}
+
+impl PaymentError {
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, PaymentError::Api { status: 404, .. })
+    }
+}
+
+impl fmt::Display for PaymentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PaymentError::Http(e) => write!(f, "Payment provider request failed: {}", e),
+            PaymentError::Api { status, detail } => write!(f, "Payment provider error ({}): {}", status, detail),
+        }
+    }
+}
+
+impl std::error::Error for PaymentError {}
+
+impl From<reqwest::Error> for PaymentError {
+    fn from(e: reqwest::Error) -> Self {
+        PaymentError::Http(e)
+    }
+}
+
+/// Client for the payment provider, kept behind a trait so it can be swapped for a fake.
+#[async_trait]
+pub trait PaymentClient: Send + Sync {
+    async fn create_payment(&self, request: &PaymentRequest) -> Result<Payment, PaymentError>;
+
+    async fn get_payment(&self, payment_id: &str) -> Result<Payment, PaymentError>;
+}
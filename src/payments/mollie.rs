@@ -0,0 +1,239 @@
+use super::{Payment, PaymentClient, PaymentError, PaymentRequest};
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::json;
+use crate::config::MollieConfig;
+use std::time::Duration;
+
+/// Mollie API client. The API URL can be overridden to target a local fake server.
+pub struct MollieClient {
+    http: reqwest::Client,
+    api_url: String,
+    api_key: String,
+    redirect_url: String,
+    webhook_url: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct MolliePayment {
+    id: String,
+    status: String,
+    #[serde(rename = "_links")]
+    links: MollieLinks,
+}
+
+#[derive(Deserialize)]
+struct MollieLinks {
+    checkout: Option<MollieLink>,
+}
+
+#[derive(Deserialize)]
+struct MollieLink {
+    href: String,
+}
+
+impl From<MolliePayment> for Payment {
+    fn from(payment: MolliePayment) -> Self {
+        Payment {
+            id: payment.id,
+            status: payment.status,
+            checkout_url: payment.links.checkout.map(|link| link.href),
+        }
+    }
+}
+
+impl MollieClient {
+    /// Requests taking longer than `timeout` fail, so a stalled Mollie can't hold orders and webhooks forever.
+    pub fn new(api_url: String, api_key: String, redirect_url: String, webhook_url: Option<String>, timeout: Duration) -> Self {
+        MollieClient {
+            http: reqwest::Client::builder()
+                .timeout(timeout)
+                .build()
+                .expect("Error building the HTTP client"),
+            api_url: api_url.trim_end_matches('/').to_string(),
+            api_key,
+            redirect_url,
+            webhook_url,
+        }
+    }
+
+    pub fn from_config(config: &MollieConfig) -> Self {
+        MollieClient::new(
+            config.api_url.clone(),
+            config.api_key.clone(),
+            config.redirect_url.clone(),
+            config.webhook_url.clone(),
+            config.timeout,
+        )
+    }
+
+    async fn parse_response(response: reqwest::Response) -> Result<Payment, PaymentError> {
+        let status = response.status();
+        if !status.is_success() {
+            let detail = response.text().await.unwrap_or_default();
+            return Err(PaymentError::Api { status: status.as_u16(), detail });
+        }
+        Ok(response.json::<MolliePayment>().await?.into())
+    }
+}
+
+#[async_trait]
+impl PaymentClient for MollieClient {
+    async fn create_payment(&self, request: &PaymentRequest) -> Result<Payment, PaymentError> {
+        let order_id = request.order_id.to_string();
+        let mut body = json!({
+            "amount": {
+                "currency": "EUR",
+                "value": format!("{:.2}", request.amount),
+            },
+            "description": request.description,
+            "redirectUrl": self.redirect_url.replace("{order_id}", &order_id),
+            "metadata": { "order_id": order_id },
+        });
+        if let Some(webhook_url) = &self.webhook_url {
+            body["webhookUrl"] = json!(webhook_url);
+        }
+
+        let response = self.http
+            .post(format!("{}/payments", self.api_url))
+            .bearer_auth(&self.api_key)
+            .json(&body)
+            .send()
+            .await?;
+
+        Self::parse_response(response).await
+    }
+
+    async fn get_payment(&self, payment_id: &str) -> Result<Payment, PaymentError> {
+        let response = self.http
+            .get(format!("{}/payments/{}", self.api_url, payment_id))
+            .bearer_auth(&self.api_key)
+            .send()
+            .await?;
+
+        Self::parse_response(response).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+    use serde_json::Value;
+    use std::sync::{Arc, Mutex};
+    use uuid::Uuid;
+
+    const API_KEY: &str = "test_key";
+
+    type Received = Arc<Mutex<Vec<Value>>>;
+
+    fn authorized(req: &HttpRequest) -> bool {
+        req.headers()
+            .get("Authorization")
+            .and_then(|value| value.to_str().ok())
+            == Some(&format!("Bearer {}", API_KEY))
+    }
+
+    fn payment_json(id: &str, status: &str) -> Value {
+        json!({
+            "id": id,
+            "status": status,
+            "_links": { "checkout": { "href": format!("https://mollie.test/checkout/{}", id) } },
+        })
+    }
+
+    async fn create(req: HttpRequest, received: web::Data<Received>, body: web::Json<Value>) -> HttpResponse {
+        if !authorized(&req) {
+            return HttpResponse::Unauthorized().finish();
+        }
+        received.lock().unwrap().push(body.into_inner());
+        HttpResponse::Created().json(payment_json("tr_created", "open"))
+    }
+
+    async fn get(req: HttpRequest, payment_id: web::Path<String>) -> HttpResponse {
+        if !authorized(&req) {
+            return HttpResponse::Unauthorized().finish();
+        }
+        match payment_id.as_str() {
+            "tr_paid" => HttpResponse::Ok().json(payment_json("tr_paid", "paid")),
+            _ => HttpResponse::NotFound().json(json!({ "status": 404, "title": "Not Found" })),
+        }
+    }
+
+    async fn stalled() -> HttpResponse {
+        actix_web::rt::time::sleep(Duration::from_secs(5)).await;
+        HttpResponse::Ok().json(payment_json("tr_stalled", "paid"))
+    }
+
+    /// Starts a fake Mollie API on a free local port and returns its base URL.
+    fn start_fake_mollie(received: Received) -> String {
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(web::Data::new(received.clone()))
+                .route("/v2/payments", web::post().to(create))
+                .route("/v2/payments/tr_stalled", web::get().to(stalled))
+                .route("/v2/payments/{id}", web::get().to(get))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let address = server.addrs()[0];
+        actix_web::rt::spawn(server.run());
+        format!("http://{}/v2", address)
+    }
+
+    fn client(api_url: String) -> MollieClient {
+        MollieClient::new(
+            api_url,
+            API_KEY.to_string(),
+            "https://front.test/orders/{order_id}".to_string(),
+            Some("https://api.test/webhooks/mollie".to_string()),
+            Duration::from_millis(500),
+        )
+    }
+
+    #[actix_web::test]
+    async fn create_payment_sends_the_order_and_returns_the_checkout() {
+        let received = Received::default();
+        let client = client(start_fake_mollie(received.clone()));
+        let order_id = Uuid::new_v4();
+
+        let payment = client
+            .create_payment(&PaymentRequest {
+                order_id,
+                amount: 12.5,
+                description: format!("Order {}", order_id),
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(payment.id, "tr_created");
+        assert_eq!(payment.status, "open");
+        assert_eq!(payment.checkout_url.as_deref(), Some("https://mollie.test/checkout/tr_created"));
+
+        let body = received.lock().unwrap().pop().unwrap();
+        assert_eq!(body["amount"], json!({ "currency": "EUR", "value": "12.50" }));
+        assert_eq!(body["redirectUrl"], format!("https://front.test/orders/{}", order_id));
+        assert_eq!(body["webhookUrl"], "https://api.test/webhooks/mollie");
+        assert_eq!(body["metadata"]["order_id"], order_id.to_string());
+    }
+
+    #[actix_web::test]
+    async fn get_payment_returns_the_status_or_not_found() {
+        let client = client(start_fake_mollie(Received::default()));
+
+        let payment = client.get_payment("tr_paid").await.unwrap();
+        assert_eq!(payment.status, "paid");
+
+        let error = client.get_payment("tr_unknown").await.unwrap_err();
+        assert!(error.is_not_found());
+    }
+
+    #[actix_web::test]
+    async fn stalled_requests_time_out() {
+        let client = client(start_fake_mollie(Received::default()));
+
+        let error = client.get_payment("tr_stalled").await.unwrap_err();
+        assert!(matches!(error, PaymentError::Http(ref e) if e.is_timeout()), "{}", error);
+    }
+}
@@ -0,0 +1,48 @@
+use actix_web::web;
+use diesel::r2d2::{self, ConnectionManager};
+use diesel::PgConnection;
+use crate::errors::AppError;
+
+pub type DbPool = r2d2::Pool<ConnectionManager<PgConnection>>;
+
+/// Access to the database from async handlers.
+///
+/// Diesel and r2d2 are blocking, so queries run on actix's blocking thread pool.
+/// A slow query then holds a blocking thread instead of stalling every request
+/// handled by the same worker.
+#[derive(Clone)]
+pub struct Repository {
+    pool: DbPool,
+}
+
+impl Repository {
+    pub fn new(pool: DbPool) -> Self {
+        Repository { pool }
+    }
+
+    /// Runs `query` with a pooled connection, off the async workers.
+    pub async fn run<T, E, F>(&self, query: F) -> Result<T, AppError>
+    where
+        F: FnOnce(&mut PgConnection) -> Result<T, E> + Send + 'static,
+        T: Send + 'static,
+        E: Into<AppError> + Send + 'static,
+    {
+        let pool = self.pool.clone();
+        blocking(move || {
+            let mut connection = pool.get()?;
+            query(&mut connection).map_err(Into::into)
+        })
+        .await?
+    }
+}
+
+/// Runs CPU-heavy work, such as password hashing, off the async workers.
+pub async fn blocking<T, F>(work: F) -> Result<T, AppError>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    web::block(work)
+        .await
+        .map_err(|e| AppError::internal("blocking_error", e))
+}
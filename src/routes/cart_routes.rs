@@ -0,0 +1,23 @@
+use crate::controllers::cart_controller;
+use crate::middlewares::{cors, token_validation};
+use actix_web::web;
+
+pub fn configure_cart_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/cart")
+            .wrap(token_validation::Authentication)
+            .wrap(cors::api())
+            .service(
+                web::resource("")
+                    .route(web::get().to(cart_controller::get_cart))
+                    .route(web::delete().to(cart_controller::clear_cart)),
+            )
+            .service(web::resource("/products").route(web::post().to(cart_controller::add_product)))
+            .service(
+                web::resource("/products/{product_id}")
+                    .route(web::put().to(cart_controller::update_product))
+                    .route(web::delete().to(cart_controller::remove_product)),
+            )
+            .service(web::resource("/checkout").route(web::post().to(cart_controller::checkout))),
+    );
+}
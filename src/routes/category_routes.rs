@@ -0,0 +1,26 @@
+use crate::controllers::category_controller;
+use crate::middlewares::authorization::RequireRole;
+use crate::middlewares::{cors, token_validation};
+use crate::models::role::Role;
+use actix_web::web;
+
+pub fn configure_category_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin/categories")
+            .wrap(RequireRole(Role::Staff))
+            .wrap(token_validation::Authentication)
+            .wrap(cors::admin())
+            .service(
+                web::resource("")
+                    .route(web::get().to(category_controller::get_categories))
+                    .route(web::post().to(category_controller::create_category)),
+            )
+            // Registered before "/{id}" so "order" isn't taken for a category id
+            .service(web::resource("/order").route(web::put().to(category_controller::reorder_categories)))
+            .service(
+                web::resource("/{id}")
+                    .route(web::put().to(category_controller::update_category))
+                    .route(web::delete().to(category_controller::delete_category)),
+            ),
+    );
+}
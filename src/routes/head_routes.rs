@@ -1,10 +1,12 @@
 // src/routes/head_routes.rs
 
 use actix_web::{web, HttpResponse};
+use crate::middlewares::cors;
 
 pub fn configure_head_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::resource("/")
-            .route(web::head().to(|| HttpResponse::Ok()))
+            .wrap(cors::public())
+            .route(web::head().to(HttpResponse::Ok))
     );
 }
\ No newline at end of file
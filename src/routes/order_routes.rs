@@ -0,0 +1,19 @@
+use crate::controllers::order_controller;
+use crate::middlewares::{cors, token_validation};
+use actix_web::web;
+
+pub fn configure_order_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/orders")
+            .wrap(token_validation::Authentication)
+            .wrap(cors::api())
+            .route(web::get().to(order_controller::get_orders))
+            .route(web::post().to(order_controller::place_order)),
+    );
+    cfg.service(
+        web::resource("/orders/{id}")
+            .wrap(token_validation::Authentication)
+            .wrap(cors::api())
+            .route(web::get().to(order_controller::get_order)),
+    );
+}
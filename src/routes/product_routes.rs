@@ -1,11 +1,28 @@
 use crate::controllers::product_controller;
 use actix_web::web;
-//use crate::middlewares::token_validation; // Import your middleware
+use crate::middlewares::authorization::RequireRole;
+use crate::middlewares::{cors, token_validation};
+use crate::models::role::Role;
 
 pub fn configure_product_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::resource("/products")
-            //.wrap(token_validation::Authentication) 
+            .wrap(cors::public())
             .route(web::get().to(product_controller::translated_products_handler)),
     );
-}
\ No newline at end of file
+    cfg.service(
+        web::scope("/admin/products")
+            .wrap(RequireRole(Role::Staff))
+            .wrap(token_validation::Authentication)
+            .wrap(cors::admin())
+            .service(web::resource("").route(web::post().to(product_controller::create_product)))
+            .service(
+                web::resource("/{id}")
+                    .route(web::get().to(product_controller::get_product))
+                    .route(web::put().to(product_controller::update_product))
+                    .route(web::delete().to(product_controller::delete_product)),
+            )
+            .service(web::resource("/{id}/activate").route(web::post().to(product_controller::activate_product)))
+            .service(web::resource("/{id}/deactivate").route(web::post().to(product_controller::deactivate_product))),
+    );
+}
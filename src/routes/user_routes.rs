@@ -1,10 +1,37 @@
 use crate::controllers::user_controller;
+use crate::middlewares::authorization::RequireRole;
+use crate::middlewares::{cors, token_validation};
+use crate::models::role::Role;
 use actix_web::web;
 
 pub fn configure_user_routes(cfg: &mut web::ServiceConfig) {
     //cfg.service(web::resource("/users").route(web::get().to(user_controller::get_all_users)));
-    cfg.service(web::resource("/sign-up").route(web::post().to(user_controller::sign_up)));
-    cfg.service(web::resource("/sign-in").route(web::post().to(user_controller::sign_in)));
-    cfg.service(web::resource("/refresh-token").route(web::post().to(user_controller::refresh_token)));
+    cfg.service(web::resource("/sign-up").wrap(cors::api()).route(web::post().to(user_controller::sign_up)));
+    cfg.service(web::resource("/sign-in").wrap(cors::api()).route(web::post().to(user_controller::sign_in)));
+    cfg.service(web::resource("/refresh-token").wrap(cors::api()).route(web::post().to(user_controller::refresh_token)));
+    cfg.service(web::resource("/verify-email").wrap(cors::api()).route(web::post().to(user_controller::verify_email)));
+    cfg.service(
+        web::resource("/resend-verification-email")
+            .wrap(token_validation::Authentication)
+            .wrap(cors::api())
+            .route(web::post().to(user_controller::resend_verification_email)),
+    );
+    cfg.service(web::resource("/forgot-password").wrap(cors::api()).route(web::post().to(user_controller::forgot_password)));
+    cfg.service(web::resource("/reset-password").wrap(cors::api()).route(web::post().to(user_controller::reset_password)));
+    cfg.service(web::resource("/sign-out").wrap(cors::api()).route(web::post().to(user_controller::sign_out)));
+    cfg.service(
+        web::resource("/sign-out-all")
+            .wrap(token_validation::Authentication)
+            .wrap(cors::api())
+            .route(web::post().to(user_controller::sign_out_all)),
+    );
+    cfg.service(
+        web::scope("/admin/users")
+            .wrap(RequireRole(Role::Admin))
+            .wrap(token_validation::Authentication)
+            .wrap(cors::admin())
+            .service(web::resource("").route(web::get().to(user_controller::get_all_users)))
+            .service(web::resource("/{id}/role").route(web::put().to(user_controller::set_user_role))),
+    );
 
 }
\ No newline at end of file
@@ -0,0 +1,6 @@
+use crate::controllers::webhook_controller;
+use actix_web::web;
+
+pub fn configure_webhook_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/webhooks/mollie").route(web::post().to(webhook_controller::mollie_webhook)));
+}
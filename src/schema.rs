@@ -11,11 +11,22 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    cart_product (user_id, product_id) {
+        user_id -> Uuid,
+        product_id -> Uuid,
+        created_at -> Nullable<Timestamp>,
+        updated_at -> Nullable<Timestamp>,
+        quantity -> Int4,
+    }
+}
+
 diesel::table! {
     order_product (order_id, product_id) {
         order_id -> Uuid,
         product_id -> Uuid,
         quantity -> Int4,
+        unit_price -> Float8,
     }
 }
 
@@ -32,6 +43,18 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    password_reset_tokens (id) {
+        id -> Uuid,
+        created_at -> Nullable<Timestamp>,
+        updated_at -> Nullable<Timestamp>,
+        user_id -> Uuid,
+        token_hash -> Text,
+        expires_at -> Timestamp,
+        used_at -> Nullable<Timestamp>,
+    }
+}
+
 diesel::table! {
     product_categories (id) {
         id -> Uuid,
@@ -83,6 +106,29 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    refresh_tokens (id) {
+        id -> Uuid,
+        created_at -> Nullable<Timestamp>,
+        updated_at -> Nullable<Timestamp>,
+        user_id -> Uuid,
+        family_id -> Uuid,
+        token_hash -> Text,
+        expires_at -> Timestamp,
+        used_at -> Nullable<Timestamp>,
+        revoked_at -> Nullable<Timestamp>,
+        access_token_jti -> Nullable<Uuid>,
+    }
+}
+
+diesel::table! {
+    revoked_access_tokens (jti) {
+        jti -> Uuid,
+        created_at -> Nullable<Timestamp>,
+        expires_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     users (id) {
         id -> Uuid,
@@ -94,25 +140,34 @@ diesel::table! {
         password -> Text,
         salt -> Text,
         remember_token -> Nullable<Text>,
+        role -> Text,
     }
 }
 
 diesel::joinable!(attachments -> products (product_id));
+diesel::joinable!(cart_product -> products (product_id));
+diesel::joinable!(cart_product -> users (user_id));
 diesel::joinable!(order_product -> orders (order_id));
 diesel::joinable!(order_product -> products (product_id));
+diesel::joinable!(password_reset_tokens -> users (user_id));
 diesel::joinable!(product_category_translations -> product_categories (product_category_id));
 diesel::joinable!(product_product_category -> product_categories (product_category_id));
 diesel::joinable!(product_product_category -> products (product_id));
 diesel::joinable!(product_translations -> products (product_id));
+diesel::joinable!(refresh_tokens -> users (user_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
     attachments,
+    cart_product,
     order_product,
     orders,
+    password_reset_tokens,
     product_categories,
     product_category_translations,
     product_product_category,
     product_translations,
     products,
+    refresh_tokens,
+    revoked_access_tokens,
     users,
 );
@@ -0,0 +1,272 @@
+use diesel::pg::{Pg, PgConnection};
+use diesel::prelude::*;
+use diesel::query_builder::{QueryBuilder, QueryFragment};
+use diesel::sql_query;
+use std::fmt;
+use crate::schema::*;
+
+/// Tables of `src/schema.rs` whose columns are missing from the database.
+#[derive(Debug)]
+pub struct SchemaDrift(pub Vec<(&'static str, String)>);
+
+impl fmt::Display for SchemaDrift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The database doesn't match src/schema.rs, are all migrations applied?")?;
+        for (table, error) in &self.0 {
+            write!(f, "\n  {}: {}", table, error.trim())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for SchemaDrift {}
+
+/// Selects every column `src/schema.rs` declares for each table, without reading any row,
+/// so a missing table or column is reported at startup instead of on the first request using it.
+pub fn check(conn: &mut PgConnection) -> Result<(), SchemaDrift> {
+    let mut errors = Vec::new();
+
+    macro_rules! check_tables {
+        ($($table:ident),* $(,)?) => {
+            $(
+                if let Err(e) = select_no_rows(conn, $table::table.select($table::all_columns)) {
+                    errors.push((stringify!($table), e.to_string()));
+                }
+            )*
+        };
+    }
+
+    check_tables!(
+        attachments,
+        cart_product,
+        order_product,
+        orders,
+        password_reset_tokens,
+        product_categories,
+        product_category_translations,
+        product_product_category,
+        product_translations,
+        products,
+        refresh_tokens,
+        revoked_access_tokens,
+        users,
+    );
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(SchemaDrift(errors))
+    }
+}
+
+fn select_no_rows<Q: QueryFragment<Pg>>(conn: &mut PgConnection, query: Q) -> QueryResult<()> {
+    let mut query_builder = <Pg as diesel::backend::Backend>::QueryBuilder::default();
+    query.to_sql(&mut query_builder, &Pg)?;
+    sql_query(format!("{} LIMIT 0", query_builder.finish())).execute(conn)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::{run_pending, MIGRATIONS};
+    use diesel::sql_types::Text;
+    use diesel_migrations::MigrationHarness;
+    use std::collections::BTreeMap;
+    use std::env;
+
+    /// Postgres type and nullability of each column, keyed by table and column.
+    type Columns = BTreeMap<(String, String), (String, bool)>;
+
+    #[derive(QueryableByName)]
+    struct InformationSchemaColumn {
+        #[diesel(sql_type = Text)]
+        table_name: String,
+        #[diesel(sql_type = Text)]
+        column_name: String,
+        #[diesel(sql_type = Text)]
+        data_type: String,
+        #[diesel(sql_type = Text)]
+        is_nullable: String,
+    }
+
+    /// Columns declared by the `table!` blocks of `src/schema.rs`.
+    fn declared_columns() -> Columns {
+        let mut columns = Columns::new();
+        let mut table = None;
+        for line in include_str!("schema.rs").lines().map(str::trim) {
+            if line == "diesel::table! {" {
+                table = Some(String::new());
+            } else if line == "}" {
+                table = None;
+            } else if let Some(table) = table.as_mut().filter(|table| table.is_empty()) {
+                *table = line.split_whitespace().next().unwrap().to_string();
+            } else if let (Some(table), Some((column, sql_type))) = (&table, line.split_once(" -> ")) {
+                let sql_type = sql_type.trim_end_matches(',');
+                let (sql_type, nullable) = match sql_type.strip_prefix("Nullable<").and_then(|inner| inner.strip_suffix('>')) {
+                    Some(inner) => (inner, true),
+                    None => (sql_type, false),
+                };
+                let column = column.trim_start_matches("r#").to_string();
+                columns.insert((table.clone(), column), (postgres_type(sql_type).to_string(), nullable));
+            }
+        }
+        columns
+    }
+
+    /// The `information_schema` name of a Diesel SQL type.
+    fn postgres_type(sql_type: &str) -> &'static str {
+        match sql_type {
+            "Bool" => "boolean",
+            "Int2" => "smallint",
+            "Int4" => "integer",
+            "Int8" => "bigint",
+            "Float4" => "real",
+            "Float8" => "double precision",
+            "Numeric" => "numeric",
+            "Text" => "text",
+            "Varchar" => "character varying",
+            "Date" => "date",
+            "Timestamp" => "timestamp without time zone",
+            "Timestamptz" => "timestamp with time zone",
+            "Uuid" => "uuid",
+            "Jsonb" => "jsonb",
+            _ => panic!("Unknown SQL type {} in src/schema.rs, add it to postgres_type", sql_type),
+        }
+    }
+
+    fn database_columns(conn: &mut PgConnection) -> Columns {
+        sql_query(
+            "SELECT table_name::text, column_name::text, data_type::text, is_nullable::text \
+             FROM information_schema.columns \
+             WHERE table_schema = 'public' AND table_name <> '__diesel_schema_migrations'",
+        )
+        .load::<InformationSchemaColumn>(conn)
+        .unwrap()
+        .into_iter()
+        .map(|column| ((column.table_name, column.column_name), (column.data_type, column.is_nullable == "YES")))
+        .collect()
+    }
+
+    /// Every column whose type or nullability differs, or that only one side has.
+    fn column_differences(declared: &Columns, database: &Columns) -> Vec<String> {
+        let describe = |column: Option<&(String, bool)>| match column {
+            Some((data_type, true)) => format!("nullable {}", data_type),
+            Some((data_type, false)) => format!("not null {}", data_type),
+            None => "missing".to_string(),
+        };
+        let mut keys: Vec<_> = declared.keys().chain(database.keys()).collect();
+        keys.sort();
+        keys.dedup();
+        keys.into_iter()
+            .filter(|key| declared.get(*key) != database.get(*key))
+            .map(|key @ (table, column)| {
+                format!("{}.{}: {} in src/schema.rs, {} in the database", table, column, describe(declared.get(key)), describe(database.get(key)))
+            })
+            .collect()
+    }
+
+    fn assert_columns_match(conn: &mut PgConnection) {
+        let differences = column_differences(&declared_columns(), &database_columns(conn));
+        assert!(differences.is_empty(), "src/schema.rs doesn't match the migrations:\n  {}", differences.join("\n  "));
+    }
+
+    /// Database created for one test run, dropped when the guard goes away.
+    struct ScratchDatabase {
+        server_url: String,
+        name: String,
+    }
+
+    impl ScratchDatabase {
+        fn create(server_url: &str) -> ScratchDatabase {
+            let database = ScratchDatabase {
+                server_url: server_url.trim_end_matches('/').to_string(),
+                name: format!("tsb_schema_check_{}", std::process::id()),
+            };
+            let mut conn = database.maintenance_connection().expect("Could not connect to SCHEMA_CHECK_DATABASE_URL");
+            sql_query(format!("DROP DATABASE IF EXISTS {}", database.name)).execute(&mut conn).unwrap();
+            sql_query(format!("CREATE DATABASE {}", database.name)).execute(&mut conn).unwrap();
+            database
+        }
+
+        fn maintenance_connection(&self) -> ConnectionResult<PgConnection> {
+            PgConnection::establish(&format!("{}/postgres", self.server_url))
+        }
+
+        fn connect(&self) -> PgConnection {
+            PgConnection::establish(&format!("{}/{}", self.server_url, self.name)).unwrap()
+        }
+    }
+
+    impl Drop for ScratchDatabase {
+        fn drop(&mut self) {
+            if let Ok(mut conn) = self.maintenance_connection() {
+                let _ = sql_query(format!("DROP DATABASE IF EXISTS {}", self.name)).execute(&mut conn);
+            }
+        }
+    }
+
+    fn column(table: &str, column: &str, data_type: &str, nullable: bool) -> ((String, String), (String, bool)) {
+        ((table.to_string(), column.to_string()), (data_type.to_string(), nullable))
+    }
+
+    #[test]
+    fn declared_columns_are_read_from_the_schema() {
+        let declared = declared_columns();
+
+        for (key, value) in [
+            column("cart_product", "quantity", "integer", false),
+            column("orders", "mollie_payment_id", "text", true),
+            column("product_categories", "order", "integer", true),
+            column("password_reset_tokens", "expires_at", "timestamp without time zone", false),
+        ] {
+            assert_eq!(declared.get(&key), Some(&value), "{:?}", key);
+        }
+        assert!(declared.keys().all(|(table, _)| table != "diesel"));
+    }
+
+    #[test]
+    fn type_nullability_and_missing_columns_are_reported() {
+        let declared = Columns::from([
+            column("orders", "id", "uuid", false),
+            column("orders", "status", "text", false),
+            column("orders", "paid_at", "timestamp without time zone", true),
+            column("cart_product", "quantity", "integer", false),
+        ]);
+        let database = Columns::from([
+            column("orders", "id", "uuid", false),
+            column("orders", "status", "text", true),
+            column("orders", "note", "text", true),
+            column("cart_product", "quantity", "bigint", false),
+        ]);
+
+        assert_eq!(column_differences(&declared, &database), vec![
+            "cart_product.quantity: not null integer in src/schema.rs, not null bigint in the database",
+            "orders.note: missing in src/schema.rs, nullable text in the database",
+            "orders.paid_at: nullable timestamp without time zone in src/schema.rs, missing in the database",
+            "orders.status: not null text in src/schema.rs, nullable text in the database",
+        ]);
+        assert!(column_differences(&declared, &declared).is_empty());
+    }
+
+    /// Applies every migration to a throwaway database and checks it against `src/schema.rs`,
+    /// down to the type and nullability of each column, then reverts them all and applies
+    /// them again. Run it with
+    /// `SCHEMA_CHECK_DATABASE_URL=postgres://postgres@localhost:5432 cargo test -- --ignored`.
+    #[test]
+    #[ignore = "needs a Postgres server in SCHEMA_CHECK_DATABASE_URL"]
+    fn migrations_match_the_schema() {
+        let server_url = env::var("SCHEMA_CHECK_DATABASE_URL").expect("SCHEMA_CHECK_DATABASE_URL must be set");
+        let database = ScratchDatabase::create(&server_url);
+        let mut conn = database.connect();
+
+        run_pending(&mut conn).unwrap();
+        check(&mut conn).unwrap_or_else(|drift| panic!("{}", drift));
+        assert_columns_match(&mut conn);
+
+        conn.revert_all_migrations(MIGRATIONS).unwrap();
+        run_pending(&mut conn).unwrap();
+        check(&mut conn).unwrap_or_else(|drift| panic!("{}", drift));
+        assert_columns_match(&mut conn);
+    }
+}
@@ -0,0 +1,102 @@
+use super::{AttemptStore, Attempts, ThrottlePolicy};
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Keeps failed attempts in memory. They are lost on restart and not shared
+/// between instances, which is fine as long as the API runs as a single process.
+#[derive(Default)]
+pub struct MemoryAttemptStore {
+    attempts: Mutex<HashMap<String, Attempts>>,
+}
+
+/// Stale entries are purged when the map grows past this size.
+const PURGE_THRESHOLD: usize = 10_000;
+
+impl MemoryAttemptStore {
+    pub fn new() -> Self {
+        MemoryAttemptStore::default()
+    }
+}
+
+impl AttemptStore for MemoryAttemptStore {
+    fn check_and_record(&self, key: &str, policy: &ThrottlePolicy, now: DateTime<Utc>) -> Option<Duration> {
+        let forget_after = policy.lock_duration;
+        let mut attempts = self.attempts.lock().unwrap_or_else(|e| e.into_inner());
+        if attempts.len() > PURGE_THRESHOLD {
+            attempts.retain(|_, entry| now - entry.last_failure < forget_after);
+        }
+
+        let entry = attempts
+            .entry(key.to_string())
+            .or_insert(Attempts { failures: 0, last_failure: now });
+        if now - entry.last_failure >= forget_after {
+            entry.failures = 0;
+        }
+        if let Some(wait) = policy.retry_after(entry, now) {
+            return Some(wait);
+        }
+        entry.failures += 1;
+        entry.last_failure = now;
+        None
+    }
+
+    fn forgive(&self, key: &str) {
+        if let Some(entry) = self.attempts.lock().unwrap_or_else(|e| e.into_inner()).get_mut(key) {
+            entry.failures = entry.failures.saturating_sub(1);
+        }
+    }
+
+    fn reset(&self, key: &str) {
+        self.attempts.lock().unwrap_or_else(|e| e.into_inner()).remove(key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    fn policy() -> ThrottlePolicy {
+        ThrottlePolicy {
+            free_attempts: 3,
+            base_delay: Duration::seconds(1),
+            max_delay: Duration::minutes(1),
+            lock_after: 10,
+            lock_duration: Duration::minutes(15),
+        }
+    }
+
+    #[test]
+    fn concurrent_attempts_only_get_the_free_ones() {
+        let store = Arc::new(MemoryAttemptStore::new());
+        let now = Utc::now();
+
+        let handles: Vec<_> = (0..32)
+            .map(|_| {
+                let store = store.clone();
+                thread::spawn(move || store.check_and_record("email:a@x.com", &policy(), now).is_none())
+            })
+            .collect();
+        let allowed = handles.into_iter().map(|handle| handle.join().unwrap()).filter(|allowed| *allowed).count();
+
+        assert_eq!(allowed, 3);
+    }
+
+    #[test]
+    fn forgiven_and_reset_attempts_no_longer_count() {
+        let store = MemoryAttemptStore::new();
+        let now = Utc::now();
+        for _ in 0..3 {
+            assert!(store.check_and_record("ip:127.0.0.1", &policy(), now).is_none());
+        }
+        assert!(store.check_and_record("ip:127.0.0.1", &policy(), now).is_some());
+
+        store.forgive("ip:127.0.0.1");
+        assert!(store.check_and_record("ip:127.0.0.1", &policy(), now).is_none());
+
+        store.reset("ip:127.0.0.1");
+        assert!(store.check_and_record("ip:127.0.0.1", &policy(), now).is_none());
+    }
+}
@@ -0,0 +1,242 @@
+pub mod memory;
+
+use actix_web::HttpRequest;
+use chrono::{DateTime, Duration, Utc};
+use std::net::IpAddr;
+use std::sync::Arc;
+use crate::config::{Config, LoginConfig};
+
+pub use self::memory::MemoryAttemptStore;
+
+/// Failed attempts recorded for a key, e.g. an email or an IP address.
+#[derive(Debug, Clone, Copy)]
+pub struct Attempts {
+    pub failures: u32,
+    pub last_failure: DateTime<Utc>,
+}
+
+/// Where failed attempts are kept, behind a trait so they can be moved to a shared store.
+pub trait AttemptStore: Send + Sync {
+    /// Returns how long the key has to wait under `policy`, or counts the attempt as a
+    /// failure straight away, in one step so concurrent attempts can't all get through
+    /// before the first failure is recorded. Failures older than the policy's lock
+    /// duration are dropped first.
+    fn check_and_record(&self, key: &str, policy: &ThrottlePolicy, now: DateTime<Utc>) -> Option<Duration>;
+
+    /// Takes back an attempt counted by `check_and_record` that turned out successful.
+    fn forgive(&self, key: &str);
+
+    fn reset(&self, key: &str);
+}
+
+/// How a key is slowed down as its failures pile up: the first `free_attempts` are
+/// not delayed, the next ones wait exponentially longer from `base_delay` up to `max_delay`,
+/// and from `lock_after` failures the key is locked for `lock_duration`.
+#[derive(Debug, Clone, Copy)]
+pub struct ThrottlePolicy {
+    pub free_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub lock_after: u32,
+    pub lock_duration: Duration,
+}
+
+impl ThrottlePolicy {
+    /// Time left before the next attempt is allowed, if any.
+    pub fn retry_after(&self, attempts: &Attempts, now: DateTime<Utc>) -> Option<Duration> {
+        let wait = if attempts.failures >= self.lock_after {
+            self.lock_duration
+        } else if attempts.failures >= self.free_attempts {
+            let exponent = (attempts.failures - self.free_attempts).min(20);
+            (self.base_delay * 2i32.pow(exponent)).min(self.max_delay)
+        } else {
+            return None;
+        };
+
+        let remaining = attempts.last_failure + wait - now;
+        (remaining > Duration::zero()).then_some(remaining)
+    }
+}
+
+/// Throttles sign-in attempts by email, to protect each account, and by client IP,
+/// to slow down credential stuffing across many accounts.
+pub struct LoginThrottle {
+    store: Arc<dyn AttemptStore>,
+    email_policy: ThrottlePolicy,
+    ip_policy: ThrottlePolicy,
+}
+
+impl LoginThrottle {
+    pub fn new(store: Arc<dyn AttemptStore>, email_policy: ThrottlePolicy, ip_policy: ThrottlePolicy) -> Self {
+        LoginThrottle { store, email_policy, ip_policy }
+    }
+
+    /// Delays grow from a second up to a minute, after 3 free attempts per account
+    /// and 20 per IP, until the configured lockout thresholds are reached.
+    pub fn from_config(config: &LoginConfig, store: Arc<dyn AttemptStore>) -> Self {
+        let lock_duration = config.lock_duration;
+        let email_policy = ThrottlePolicy {
+            free_attempts: 3,
+            base_delay: Duration::seconds(1),
+            max_delay: Duration::minutes(1),
+            lock_after: config.lock_after,
+            lock_duration,
+        };
+        let ip_policy = ThrottlePolicy {
+            free_attempts: 20,
+            base_delay: Duration::seconds(1),
+            max_delay: Duration::minutes(1),
+            lock_after: config.ip_lock_after,
+            lock_duration,
+        };
+        LoginThrottle::new(store, email_policy, ip_policy)
+    }
+
+    /// Returns how long the client has to wait before trying to sign in with this email,
+    /// if it's throttled. Otherwise the attempt is counted as failed until `record_success`.
+    pub fn check_and_record(&self, email: &str, ip: Option<&str>) -> Option<Duration> {
+        self.check_and_record_keys(&email_key(email), ip.map(ip_key).as_deref())
+    }
+
+    /// Same as `check_and_record` for password reset requests, which are counted apart from
+    /// sign-ins and never forgiven: each one sends an email, so they can't be repeated at will.
+    pub fn check_and_record_password_reset(&self, email: &str, ip: Option<&str>) -> Option<Duration> {
+        let ip_key = ip.map(|ip| format!("reset:{}", ip_key(ip)));
+        self.check_and_record_keys(&format!("reset:{}", email_key(email)), ip_key.as_deref())
+    }
+
+    /// Takes back an attempt that couldn't be checked, e.g. because the database failed.
+    pub fn forgive(&self, email: &str, ip: Option<&str>) {
+        self.store.forgive(&email_key(email));
+        if let Some(ip) = ip {
+            self.store.forgive(&ip_key(ip));
+        }
+    }
+
+    /// A successful sign-in clears the failures of the account. The IP only gets this
+    /// attempt back, as it may be trying other accounts.
+    pub fn record_success(&self, email: &str, ip: Option<&str>) {
+        self.store.reset(&email_key(email));
+        if let Some(ip) = ip {
+            self.store.forgive(&ip_key(ip));
+        }
+    }
+
+    fn check_and_record_keys(&self, email_key: &str, ip_key: Option<&str>) -> Option<Duration> {
+        let now = Utc::now();
+        if let Some(ip_key) = ip_key {
+            if let Some(wait) = self.store.check_and_record(ip_key, &self.ip_policy, now) {
+                return Some(wait);
+            }
+        }
+        let email_wait = self.store.check_and_record(email_key, &self.email_policy, now);
+        if let (Some(_), Some(ip_key)) = (email_wait, ip_key) {
+            self.store.forgive(ip_key);
+        }
+        email_wait
+    }
+}
+
+/// Address attempts are counted by: the peer address, unless it's a trusted proxy, in
+/// which case the closest untrusted address of `X-Forwarded-For` is used. Entries further
+/// left were added by the client itself and can be forged.
+pub fn client_ip(req: &HttpRequest) -> Option<String> {
+    let peer = req.peer_addr()?.ip();
+    let forwarded_for = req.headers().get("X-Forwarded-For").and_then(|value| value.to_str().ok());
+    resolve_client_ip(peer, forwarded_for, &Config::global().trusted_proxies).map(|ip| ip.to_string())
+}
+
+fn resolve_client_ip(peer: IpAddr, forwarded_for: Option<&str>, trusted_proxies: &[IpAddr]) -> Option<IpAddr> {
+    if !trusted_proxies.contains(&peer) {
+        return Some(peer);
+    }
+    // A malformed entry means the header can't be relied on, and counting attempts
+    // by the proxy address would throttle every client at once
+    for hop in forwarded_for?.rsplit(',') {
+        let hop = hop.trim().parse::<IpAddr>().ok()?;
+        if !trusted_proxies.contains(&hop) {
+            return Some(hop);
+        }
+    }
+    None
+}
+
+fn email_key(email: &str) -> String {
+    format!("email:{}", email)
+}
+
+fn ip_key(ip: &str) -> String {
+    format!("ip:{}", ip)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> ThrottlePolicy {
+        ThrottlePolicy {
+            free_attempts: 3,
+            base_delay: Duration::seconds(1),
+            max_delay: Duration::seconds(10),
+            lock_after: 8,
+            lock_duration: Duration::minutes(15),
+        }
+    }
+
+    fn attempts(failures: u32, last_failure: DateTime<Utc>) -> Attempts {
+        Attempts { failures, last_failure }
+    }
+
+    #[test]
+    fn clients_are_the_peer_unless_it_is_a_trusted_proxy() {
+        let proxy: IpAddr = "10.0.0.2".parse().unwrap();
+        let client: IpAddr = "203.0.113.7".parse().unwrap();
+
+        assert_eq!(resolve_client_ip(client, Some("198.51.100.1"), &[proxy]), Some(client));
+        assert_eq!(resolve_client_ip(proxy, None, &[]), Some(proxy));
+        assert_eq!(resolve_client_ip(proxy, Some("203.0.113.7"), &[proxy]), Some(client));
+    }
+
+    #[test]
+    fn forged_forwarded_for_entries_are_ignored() {
+        let proxy: IpAddr = "10.0.0.2".parse().unwrap();
+        let inner_proxy: IpAddr = "10.0.0.3".parse().unwrap();
+        let client: IpAddr = "203.0.113.7".parse().unwrap();
+
+        assert_eq!(resolve_client_ip(proxy, Some("1.2.3.4, 203.0.113.7"), &[proxy]), Some(client));
+        assert_eq!(resolve_client_ip(proxy, Some("1.2.3.4, 203.0.113.7, 10.0.0.3"), &[proxy, inner_proxy]), Some(client));
+        assert_eq!(resolve_client_ip(proxy, Some("203.0.113.7, garbage"), &[proxy]), None);
+        assert_eq!(resolve_client_ip(proxy, None, &[proxy]), None);
+    }
+
+    #[test]
+    fn free_attempts_are_not_delayed() {
+        let now = Utc::now();
+        assert_eq!(policy().retry_after(&attempts(0, now), now), None);
+        assert_eq!(policy().retry_after(&attempts(2, now), now), None);
+    }
+
+    #[test]
+    fn delays_double_up_to_the_max() {
+        let now = Utc::now();
+        assert_eq!(policy().retry_after(&attempts(3, now), now), Some(Duration::seconds(1)));
+        assert_eq!(policy().retry_after(&attempts(4, now), now), Some(Duration::seconds(2)));
+        assert_eq!(policy().retry_after(&attempts(6, now), now), Some(Duration::seconds(8)));
+        assert_eq!(policy().retry_after(&attempts(7, now), now), Some(Duration::seconds(10)));
+    }
+
+    #[test]
+    fn keys_are_locked_from_lock_after() {
+        let now = Utc::now();
+        assert_eq!(policy().retry_after(&attempts(8, now), now), Some(Duration::minutes(15)));
+        assert_eq!(policy().retry_after(&attempts(50, now), now), Some(Duration::minutes(15)));
+    }
+
+    #[test]
+    fn delays_count_from_the_last_failure() {
+        let now = Utc::now();
+        let last_failure = now - Duration::seconds(3);
+        assert_eq!(policy().retry_after(&attempts(5, last_failure), now), Some(Duration::seconds(1)));
+        assert_eq!(policy().retry_after(&attempts(4, last_failure), now), None);
+    }
+}
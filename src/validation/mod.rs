@@ -0,0 +1,99 @@
+pub mod password;
+
+use std::borrow::Cow;
+use std::future::Future;
+use std::ops::Deref;
+use std::pin::Pin;
+use actix_web::{dev::Payload, web, FromRequest, HttpRequest};
+use serde::de::{Deserialize, DeserializeOwned, Deserializer};
+use validator::{Validate, ValidationError};
+use crate::errors::AppError;
+use crate::locale::supported_locales;
+
+pub use self::password::validate_password;
+
+/// JSON request body that passed its validation rules. Malformed bodies are rejected
+/// with a 400, and invalid ones with a 422 listing the errors of each field.
+pub struct Validated<T>(pub T);
+
+impl<T> Validated<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for Validated<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: DeserializeOwned + Validate + 'static> FromRequest for Validated<T> {
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let json = web::Json::<T>::from_request(req, payload);
+        Box::pin(async move {
+            // Malformed bodies are already rendered by the handler of `errors::configure_extractors`
+            let value = json.await?.into_inner();
+            value.validate().map_err(AppError::Validation)?;
+            Ok(Validated(value))
+        })
+    }
+}
+
+/// Emails are compared and stored trimmed and lowercased.
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// Deserializes an email address normalized with `normalize_email`.
+pub fn normalized_email<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    let email = String::deserialize(deserializer)?;
+    Ok(normalize_email(&email))
+}
+
+/// Deserializes a string without its surrounding whitespace.
+pub fn trimmed<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    Ok(value.trim().to_string())
+}
+
+/// Custom validator for Mollie payment ids, `tr_` followed by letters and digits. The id
+/// ends up in the path of a Mollie API request, so nothing else may get through.
+pub fn validate_payment_id(id: &str) -> Result<(), ValidationError> {
+    let valid = id
+        .strip_prefix("tr_")
+        .is_some_and(|rest| !rest.is_empty() && rest.chars().all(|c| c.is_ascii_alphanumeric()));
+    if valid {
+        return Ok(());
+    }
+    Err(ValidationError::new("invalid_payment_id").with_message(Cow::Borrowed("Invalid payment id")))
+}
+
+/// Custom validator for locale fields.
+pub fn validate_locale(locale: &str) -> Result<(), ValidationError> {
+    if supported_locales().iter().any(|supported| supported == locale) {
+        return Ok(());
+    }
+    Err(ValidationError::new("unsupported_locale")
+        .with_message(Cow::Owned(format!("Locale must be one of {}", supported_locales().join(", ")))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payment_ids_are_single_path_segments() {
+        assert!(validate_payment_id("tr_WDqYK6vllg").is_ok());
+        assert!(validate_payment_id("tr_").is_err());
+        assert!(validate_payment_id("ord_WDqYK6vllg").is_err());
+        assert!(validate_payment_id("tr_x/refunds").is_err());
+        assert!(validate_payment_id("../customers/cst_1").is_err());
+        assert!(validate_payment_id("tr_x?testmode=true").is_err());
+    }
+}
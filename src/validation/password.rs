@@ -0,0 +1,77 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::fs;
+use std::sync::OnceLock;
+use validator::ValidationError;
+use crate::config::{ConfigError, PasswordConfig};
+
+/// Common passwords found in breaches, refused whatever the length policy.
+const BREACHED_PASSWORDS: &str = include_str!("breached_passwords.txt");
+
+/// Rules new passwords must follow: configured lengths, and neither the bundled list of
+/// breached passwords nor the optional configured one.
+pub struct PasswordPolicy {
+    min_length: usize,
+    max_length: usize,
+    breached: HashSet<String>,
+}
+
+fn parse_list(list: &str) -> impl Iterator<Item = String> + '_ {
+    list.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_lowercase)
+}
+
+static POLICY: OnceLock<PasswordPolicy> = OnceLock::new();
+
+impl PasswordPolicy {
+    /// Builds the policy, reading the configured list of breached passwords.
+    pub fn from_config(config: &PasswordConfig) -> Result<Self, ConfigError> {
+        let mut breached: HashSet<String> = parse_list(BREACHED_PASSWORDS).collect();
+        if let Some(path) = &config.breached_passwords_file {
+            let list = fs::read_to_string(path).map_err(|e| {
+                ConfigError(vec![format!("BREACHED_PASSWORDS_FILE {} can't be read: {}", path.display(), e)])
+            })?;
+            breached.extend(parse_list(&list));
+        }
+
+        Ok(PasswordPolicy {
+            min_length: config.min_length,
+            max_length: config.max_length,
+            breached,
+        })
+    }
+
+    /// Makes the policy available through [`PasswordPolicy::global`].
+    pub fn init(policy: PasswordPolicy) -> &'static PasswordPolicy {
+        POLICY.get_or_init(|| policy)
+    }
+
+    /// The policy of the application, which must have been initialized at startup.
+    pub fn global() -> &'static PasswordPolicy {
+        POLICY.get().expect("Password policy not initialized")
+    }
+
+    pub fn check(&self, password: &str) -> Result<(), ValidationError> {
+        let length = password.chars().count();
+        if length < self.min_length {
+            return Err(ValidationError::new("password_too_short")
+                .with_message(Cow::Owned(format!("Password must be at least {} characters long", self.min_length))));
+        }
+        if length > self.max_length {
+            return Err(ValidationError::new("password_too_long")
+                .with_message(Cow::Owned(format!("Password must be at most {} characters long", self.max_length))));
+        }
+        if self.breached.contains(&password.to_lowercase()) {
+            return Err(ValidationError::new("password_breached")
+                .with_message(Cow::Borrowed("This password is too common, please choose another one")));
+        }
+        Ok(())
+    }
+}
+
+/// Custom validator applying the password policy, for `#[validate(custom(...))]`.
+pub fn validate_password(password: &str) -> Result<(), ValidationError> {
+    PasswordPolicy::global().check(password)
+}